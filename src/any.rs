@@ -0,0 +1,234 @@
+use crate::{ascon, chacha, keccak};
+use core::fmt;
+use core::str::FromStr;
+
+/// A stream cipher algorithm.
+///
+/// Algorithms have stable names and numeric codes, that can be stored in configuration files
+/// and headers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+    /// The Ascon-based stream cipher
+    Ascon,
+    /// The Keccak-based stream cipher
+    Keccak,
+    /// The ChaCha-based stream cipher
+    ChaCha,
+}
+
+impl Algorithm {
+    /// All the supported algorithms
+    pub const ALL: [Algorithm; 3] = [Algorithm::Ascon, Algorithm::Keccak, Algorithm::ChaCha];
+
+    /// Return the name of the algorithm.
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Ascon => "ascon",
+            Algorithm::Keccak => "keccak",
+            Algorithm::ChaCha => "chacha",
+        }
+    }
+
+    /// Return the numeric code of the algorithm.
+    pub fn code(self) -> u8 {
+        match self {
+            Algorithm::Ascon => 1,
+            Algorithm::Keccak => 2,
+            Algorithm::ChaCha => 3,
+        }
+    }
+
+    /// Return the algorithm with the given numeric code.
+    pub fn from_code(code: u8) -> Result<Self, &'static str> {
+        Self::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.code() == code)
+            .ok_or("unknown algorithm")
+    }
+
+    /// Return the block size of the algorithm in bytes.
+    pub fn block_size(self) -> usize {
+        match self {
+            Algorithm::Ascon => ascon::StreamCipher::BLOCK_SIZE,
+            Algorithm::Keccak => keccak::StreamCipher::BLOCK_SIZE,
+            Algorithm::ChaCha => chacha::StreamCipher::BLOCK_SIZE,
+        }
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = &'static str;
+
+    /// Parse an algorithm name. Names are case-insensitive.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name().eq_ignore_ascii_case(name))
+            .ok_or("unknown algorithm")
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Algorithm {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Algorithm {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = Algorithm;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("an algorithm name")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, name: &str) -> Result<Algorithm, E> {
+                name.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+/// A stream cipher whose algorithm is selected at runtime.
+///
+/// Every algorithm accepts the same key and an arbitrary context. As the ChaCha-based cipher
+/// only accepts an 8-byte context identifier, the context is hashed into an identifier using
+/// `chacha::IdBuilder`, as a single part.
+#[derive(Clone, Copy)]
+pub enum AnyStreamCipher {
+    /// The Ascon-based stream cipher
+    Ascon(ascon::StreamCipher),
+    /// The Keccak-based stream cipher
+    Keccak(keccak::StreamCipher),
+    /// The ChaCha-based stream cipher
+    ChaCha(chacha::StreamCipher),
+}
+
+impl AnyStreamCipher {
+    /// The key length in bytes
+    pub const KEY_LENGTH: usize = 32;
+
+    /// Create a new state for the given algorithm, key and context.
+    ///
+    /// The key must be 32 bytes long, and must be randomly generated.
+    ///
+    /// The context is optional can be of any length. It is used to improve multi-user security.
+    pub fn new(
+        algorithm: Algorithm,
+        key: &[u8; Self::KEY_LENGTH],
+        context: impl AsRef<[u8]>,
+    ) -> Self {
+        match algorithm {
+            Algorithm::Ascon => AnyStreamCipher::Ascon(ascon::StreamCipher::new(key, context)),
+            Algorithm::Keccak => AnyStreamCipher::Keccak(keccak::StreamCipher::new(key, context)),
+            Algorithm::ChaCha => {
+                let id = chacha::IdBuilder::new().part(context).finish();
+                AnyStreamCipher::ChaCha(chacha::StreamCipher::new(key, &id))
+            }
+        }
+    }
+
+    /// Return the algorithm of the stream cipher.
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            AnyStreamCipher::Ascon(_) => Algorithm::Ascon,
+            AnyStreamCipher::Keccak(_) => Algorithm::Keccak,
+            AnyStreamCipher::ChaCha(_) => Algorithm::ChaCha,
+        }
+    }
+
+    /// Fill the given buffer with the keystream starting at the given offset.
+    ///
+    /// The offset is in bytes.
+    pub fn fill(&self, out: &mut [u8], start_offset: u64) -> Result<(), &'static str> {
+        match self {
+            AnyStreamCipher::Ascon(st) => st.fill(out, start_offset),
+            AnyStreamCipher::Keccak(st) => st.fill(out, start_offset),
+            AnyStreamCipher::ChaCha(st) => st.fill(out, start_offset),
+        }
+    }
+
+    /// Encrypt or decrypt the given buffer in place, given the offset.
+    ///
+    /// The offset is in bytes.
+    /// This function is equivalent to calling `fill` and then XORing the output with the input.
+    pub fn apply_keystream(&self, out: &mut [u8], start_offset: u64) -> Result<(), &'static str> {
+        match self {
+            AnyStreamCipher::Ascon(st) => st.apply_keystream(out, start_offset),
+            AnyStreamCipher::Keccak(st) => st.apply_keystream(out, start_offset),
+            AnyStreamCipher::ChaCha(st) => st.apply_keystream(out, start_offset),
+        }
+    }
+}
+
+impl From<ascon::StreamCipher> for AnyStreamCipher {
+    fn from(st: ascon::StreamCipher) -> Self {
+        AnyStreamCipher::Ascon(st)
+    }
+}
+
+impl From<keccak::StreamCipher> for AnyStreamCipher {
+    fn from(st: keccak::StreamCipher) -> Self {
+        AnyStreamCipher::Keccak(st)
+    }
+}
+
+impl From<chacha::StreamCipher> for AnyStreamCipher {
+    fn from(st: chacha::StreamCipher) -> Self {
+        AnyStreamCipher::ChaCha(st)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_algorithm() {
+        for algorithm in Algorithm::ALL {
+            assert_eq!(algorithm.name().parse::<Algorithm>().unwrap(), algorithm);
+            assert_eq!(Algorithm::from_code(algorithm.code()).unwrap(), algorithm);
+        }
+        assert_eq!("ChaCha".parse::<Algorithm>().unwrap(), Algorithm::ChaCha);
+        assert!("aes".parse::<Algorithm>().is_err());
+        assert!(Algorithm::from_code(0).is_err());
+    }
+
+    #[test]
+    fn test_any() {
+        let mut key = [0u8; AnyStreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let mut out = [0u8; 1000];
+        let mut expected = [0u8; 1000];
+
+        let st = AnyStreamCipher::new(Algorithm::Keccak, &key, b"test");
+        assert_eq!(st.algorithm(), Algorithm::Keccak);
+        st.fill(&mut out, 10).unwrap();
+        keccak::StreamCipher::new(&key, b"test")
+            .fill(&mut expected, 10)
+            .unwrap();
+        assert_eq!(out, expected);
+
+        let st = AnyStreamCipher::new(Algorithm::ChaCha, &key, b"test");
+        st.apply_keystream(&mut out, 10).unwrap();
+        let id = chacha::IdBuilder::new().part(b"test").finish();
+        chacha::StreamCipher::new(&key, &id)
+            .apply_keystream(&mut expected, 10)
+            .unwrap();
+        assert_eq!(out, expected);
+    }
+}
@@ -1,4 +1,6 @@
+use crate::seekable::{self, Bounded, KeystreamBlock};
 use core::cmp;
+use core::ops::DerefMut;
 
 /// An ASCON-based seekable stream cipher.
 #[derive(Clone, Copy)]
@@ -11,56 +13,116 @@ impl StreamCipher {
     /// The key length in bytes
     pub const KEY_LENGTH: usize = 32;
 
+    /// The minimum length of a key passed to `new_with_key`, in bytes
+    pub const MIN_KEY_LENGTH: usize = 16;
+
+    /// The block size in bytes
+    pub const BLOCK_SIZE: usize = 40;
+
     /// The ASCON constants
     const RKS: [u64; 12] = [
         0xf0, 0xe1, 0xd2, 0xc3, 0xb4, 0xa5, 0x96, 0x87, 0x78, 0x69, 0x5a, 0x4b,
     ];
 
+    /// The length of an exported state, in bytes
+    #[cfg(feature = "hazmat")]
+    pub const STATE_BYTES_LENGTH: usize = 2 + 40;
+
+    /// The version of the exported state format
+    #[cfg(feature = "hazmat")]
+    const STATE_VERSION: u8 = 1;
+
     /// Create a new state with the given key and context.
     ///
     /// The key must be 32 bytes long, and must be randomly generated, for example using
     /// `rand::thread_rng().gen::<[u8; 32]>()` or `getrandom::fill()`.
     ///
     /// The context is optional can be of any length. It is used to improve multi-user security.
+    ///
+    /// In order to create states for many contexts with the same key, `KeyedBase` avoids
+    /// absorbing the key every time.
     pub fn new(key: &[u8; Self::KEY_LENGTH], context: impl AsRef<[u8]>) -> Self {
-        let context = context.as_ref();
-        let st = [0x010080cc00000000, 0, 0, 0, 0];
+        KeyedBase::new(key).with_context(context)
+    }
 
-        let mut state = StreamCipher { st };
-        state.st[1] ^= u64::from_le_bytes(key[0..8].try_into().unwrap());
-        state.st[2] ^= u64::from_le_bytes(key[8..16].try_into().unwrap());
-        state.st[3] ^= u64::from_le_bytes(key[16..24].try_into().unwrap());
-        state.st[4] ^= u64::from_le_bytes(key[24..32].try_into().unwrap());
-        state.permute();
+    /// Create a new state with a key of arbitrary length, and the given context.
+    ///
+    /// Keys of `KEY_LENGTH` bytes are used as-is, and this is then equivalent to `new`.
+    /// Other keys must be at least `MIN_KEY_LENGTH` bytes long. They are first expanded into
+    /// a `KEY_LENGTH`-byte key, by absorbing them along with their length into the sponge.
+    ///
+    /// The security level is `8 * key.len()` bits for keys shorter than `KEY_LENGTH` bytes,
+    /// and 256 bits for longer keys.
+    pub fn new_with_key(key: &[u8], context: impl AsRef<[u8]>) -> Result<Self, &'static str> {
+        if let Ok(key) = key.try_into() {
+            return Ok(Self::new(key, context));
+        }
+        if key.len() < Self::MIN_KEY_LENGTH {
+            return Err("key is too short");
+        }
+        let (head, tail) = key.split_at(cmp::min(key.len(), Self::KEY_LENGTH));
+        let mut padded = [0u8; Self::KEY_LENGTH];
+        padded[..head.len()].copy_from_slice(head);
+        let mut expanded = [0u8; Self::KEY_LENGTH];
+        ContextBuilder::new(&padded)
+            .part(b"seekable-stream-cipher/key-expansion")
+            .part_u64(key.len() as u64)
+            .part(tail)
+            .finish()
+            .fill(&mut expanded, 0)?;
+        let st = Self::new(&expanded, context);
+        crate::zeroize(&mut padded);
+        crate::zeroize(&mut expanded);
+        Ok(st)
+    }
 
-        let mut context = context;
-
-        while context.len() > 32 {
-            let context_part_len = 32;
-            state.st[0] ^= u64::from_le_bytes(context[0..8].try_into().unwrap());
-            state.st[1] ^= u64::from_le_bytes(context[8..16].try_into().unwrap());
-            state.st[2] ^= u64::from_le_bytes(context[16..24].try_into().unwrap());
-            state.st[3] ^= u64::from_le_bytes(context[24..32].try_into().unwrap());
-            context = &context[context_part_len..];
-            state.permute();
-        }
-
-        let context_len = context.len();
-        let mut buf = [0u8; 32];
-        buf[..context_len].copy_from_slice(context);
-        state.st[0] ^= u64::from_le_bytes(buf[0..8].try_into().unwrap());
-        state.st[1] ^= u64::from_le_bytes(buf[8..16].try_into().unwrap());
-        state.st[2] ^= u64::from_le_bytes(buf[16..24].try_into().unwrap());
-        state.st[3] ^= u64::from_le_bytes(buf[24..32].try_into().unwrap());
-        state.st[4] ^= 0x01;
-        state.permute();
+    /// Create a new state with the given key and context, in a `const` context.
+    ///
+    /// This is equivalent to `new`, and can be used to compute tables at compile time.
+    pub const fn new_const(key: &[u8; Self::KEY_LENGTH], context: &[u8]) -> Self {
+        let mut builder = KeyedBase::new(key).context_builder();
+        builder.absorb_const(context);
+        builder.finish()
+    }
 
-        state.st[0] ^= u64::from_le_bytes(key[0..8].try_into().unwrap());
-        state.st[1] ^= u64::from_le_bytes(key[8..16].try_into().unwrap());
-        state.st[2] ^= u64::from_le_bytes(key[16..24].try_into().unwrap());
-        state.st[3] ^= u64::from_le_bytes(key[24..32].try_into().unwrap());
+    /// Export the state, so that it can be cached and later restored with `from_state_bytes`.
+    ///
+    /// The serialized state includes an algorithm identifier and a format version.
+    ///
+    /// # Security
+    ///
+    /// The state is as sensitive as the key: it can be used to compute the key stream.
+    /// It must be stored and erased with the same care as the key.
+    #[cfg(feature = "hazmat")]
+    pub fn to_state_bytes(&self) -> [u8; Self::STATE_BYTES_LENGTH] {
+        let mut out = [0u8; Self::STATE_BYTES_LENGTH];
+        out[0] = crate::any::Algorithm::Ascon.code();
+        out[1] = Self::STATE_VERSION;
+        for (i, x) in self.st.iter().enumerate() {
+            out[2 + i * 8..][..8].copy_from_slice(&x.to_le_bytes());
+        }
+        out
+    }
 
-        state
+    /// Restore a state previously exported with `to_state_bytes`.
+    ///
+    /// The algorithm identifier and the format version are verified.
+    #[cfg(feature = "hazmat")]
+    pub fn from_state_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        if bytes.len() != Self::STATE_BYTES_LENGTH {
+            return Err("invalid state length");
+        }
+        if bytes[0] != crate::any::Algorithm::Ascon.code() {
+            return Err("state was exported from a different algorithm");
+        }
+        if bytes[1] != Self::STATE_VERSION {
+            return Err("unsupported state version");
+        }
+        let mut st = [0u64; 5];
+        for (i, x) in st.iter_mut().enumerate() {
+            *x = crate::load_u64_le(bytes, 2 + i * 8);
+        }
+        Ok(StreamCipher { st })
     }
 
     /// Squeeze a 40-byte block, and store it in the given buffer.
@@ -92,6 +154,21 @@ impl StreamCipher {
         }
     }
 
+    /// Squeeze a 40-byte block, add it to the input, and store the result in the output buffer.
+    #[inline(always)]
+    fn apply_rate_inout(mut self, input: &[u8], output: &mut [u8], block_offset: u64) {
+        self.st[4] ^= block_offset;
+        let mask = self.st;
+        self.permute();
+        for (x, mask) in self.st.iter_mut().zip(mask) {
+            *x ^= mask;
+        }
+        for i in 0..5 {
+            let x = u64::from_le_bytes(input[i * 8..][..8].try_into().unwrap());
+            output[i * 8..][..8].copy_from_slice(&(self.st[i] ^ x).to_le_bytes());
+        }
+    }
+
     /// Squeeze and return a 40-byte block.
     #[inline(always)]
     fn squeeze_rate(self, block_offset: u64) -> [u8; 40] {
@@ -100,34 +177,98 @@ impl StreamCipher {
         out
     }
 
+    /// Squeeze and return a block as 5 words.
+    #[inline(always)]
+    const fn squeeze_words(mut self, block_offset: u64) -> [u64; 5] {
+        self.st[4] ^= block_offset;
+        let mask = self.st;
+        self.permute();
+        let mut i = 0;
+        while i < 5 {
+            self.st[i] ^= mask[i];
+            i += 1;
+        }
+        self.st
+    }
+
     /// Fill the given buffer with the keystream starting at the given offset.
     ///
     /// The offset is in bytes.
     ///
     /// The key stream is deterministic: the same key, context and offset will always produce the same output.
-    pub fn fill(&self, mut out: &mut [u8], start_offset: u64) -> Result<(), &'static str> {
-        if start_offset.checked_add(out.len() as u64).is_none() {
-            return Err("offset would overflow");
-        }
-        let mut block_offset = start_offset / 40;
-        let offset_in_first_block = (start_offset % 40) as usize;
-        let bytes_to_copy = cmp::min(40 - offset_in_first_block, out.len());
-        if bytes_to_copy > 0 {
-            let rate = self.squeeze_rate(block_offset);
-            out[..bytes_to_copy].copy_from_slice(&rate[offset_in_first_block..][..bytes_to_copy]);
-            out = &mut out[bytes_to_copy..];
-        }
-        while out.len() >= 40 {
-            block_offset += 1;
-            self.store_rate(&mut out[..40], block_offset);
-            out = &mut out[40..];
-        }
-        if !out.is_empty() {
-            block_offset += 1;
-            let rate = self.squeeze_rate(block_offset);
-            out.copy_from_slice(&rate[..out.len()]);
+    pub fn fill(&self, out: &mut [u8], start_offset: u64) -> Result<(), &'static str> {
+        seekable::fill(self, out, start_offset)
+    }
+
+    /// Return `N` bytes of the keystream starting at the given offset, in a `const` context.
+    ///
+    /// The offset is in bytes.
+    ///
+    /// This is equivalent to `fill`, and can be used to compute tables at compile time.
+    /// Panics if the offset would overflow.
+    pub const fn fill_const<const N: usize>(&self, start_offset: u64) -> [u8; N] {
+        assert!(
+            start_offset.checked_add(N as u64).is_some(),
+            "offset would overflow"
+        );
+        let mut out = [0u8; N];
+        let mut i = 0;
+        while i < N {
+            let offset = start_offset + i as u64;
+            let words = self.squeeze_words(offset / Self::BLOCK_SIZE as u64);
+            let mut j = (offset % Self::BLOCK_SIZE as u64) as usize;
+            while j < Self::BLOCK_SIZE && i < N {
+                out[i] = (words[j / 8] >> (8 * (j % 8))) as u8;
+                i += 1;
+                j += 1;
+            }
         }
-        Ok(())
+        out
+    }
+
+    /// Fill the given buffer with 64-bit words of the keystream, starting at the given word offset.
+    ///
+    /// The offset is in 64-bit words.
+    ///
+    /// Word `i` is the little-endian interpretation of the 8 bytes of the key stream at offset `8 * i`,
+    /// so this is equivalent to calling `fill` with a byte offset of `8 * word_offset` and decoding the output.
+    pub fn fill_u64(&self, out: &mut [u64], word_offset: u64) -> Result<(), &'static str> {
+        seekable::fill_words(self, out, word_offset, |st, block_offset| {
+            st.squeeze_words(block_offset)
+        })
+    }
+
+    /// Fill the given buffer with 32-bit words of the keystream, starting at the given word offset.
+    ///
+    /// The offset is in 32-bit words.
+    ///
+    /// Word `i` is the little-endian interpretation of the 4 bytes of the key stream at offset `4 * i`,
+    /// so this is equivalent to calling `fill` with a byte offset of `4 * word_offset` and decoding the output.
+    pub fn fill_u32(&self, out: &mut [u32], word_offset: u64) -> Result<(), &'static str> {
+        seekable::fill_words(self, out, word_offset, |st, block_offset| {
+            let words = st.squeeze_words(block_offset);
+            let mut halves = [0u32; 10];
+            for (i, w) in words.iter().enumerate() {
+                halves[i * 2] = *w as u32;
+                halves[i * 2 + 1] = (*w >> 32) as u32;
+            }
+            halves
+        })
+    }
+
+    /// Fill uninitialized memory with the keystream starting at the given offset.
+    ///
+    /// The offset is in bytes.
+    ///
+    /// This is the same as `fill`, but the buffer doesn't have to be initialized first.
+    /// The initialized buffer is returned.
+    #[cfg(feature = "uninit")]
+    pub fn fill_uninit<'a>(
+        &self,
+        out: &'a mut [core::mem::MaybeUninit<u8>],
+        start_offset: u64,
+    ) -> Result<&'a mut [u8], &'static str> {
+        seekable::fill_uninit(self, out, start_offset)
     }
 
     /// Encrypt or decrypt the given buffer in place, given the offset.
@@ -142,41 +283,141 @@ impl StreamCipher {
     ///
     /// * There is no integrity.
     /// * An adversary can flip arbitrary bits in the ciphertext and the corresponding bits in the plaintext will be flipped when decrypted.
-    pub fn apply_keystream(
+    pub fn apply_keystream(&self, out: &mut [u8], start_offset: u64) -> Result<(), &'static str> {
+        seekable::apply_keystream(self, out, start_offset)
+    }
+
+    /// Encrypt or decrypt a list of buffers in place, as if they were a single contiguous buffer.
+    ///
+    /// The offset is in bytes, and is the offset of the first buffer.
+    /// Any type dereferencing to a byte slice can be used, including `std::io::IoSliceMut`.
+    ///
+    /// A key stream block shared by adjacent buffers is only computed once.
+    pub fn apply_keystream_vectored<B: DerefMut<Target = [u8]>>(
         &self,
-        mut out: &mut [u8],
+        bufs: &mut [B],
         start_offset: u64,
     ) -> Result<(), &'static str> {
-        if start_offset.checked_add(out.len() as u64).is_none() {
-            return Err("offset would overflow");
-        }
-        let mut block_offset = start_offset / 40;
-        let offset_in_first_block = (start_offset % 40) as usize;
-        let bytes_to_copy = cmp::min(40 - offset_in_first_block, out.len());
-        if bytes_to_copy > 0 {
-            let rate = self.squeeze_rate(block_offset);
-            for i in 0..bytes_to_copy {
-                out[i] ^= rate[offset_in_first_block + i];
-            }
-            out = &mut out[bytes_to_copy..];
-        }
-        while out.len() >= 40 {
-            block_offset += 1;
-            self.apply_rate(&mut out[..40], block_offset);
-            out = &mut out[40..];
-        }
-        if !out.is_empty() {
-            block_offset += 1;
-            let rate = self.squeeze_rate(block_offset);
-            for i in 0..out.len() {
-                out[i] ^= rate[i];
-            }
-        }
-        Ok(())
+        seekable::apply_keystream_vectored(self, bufs, start_offset)
+    }
+
+    /// Encrypt or decrypt disjoint ranges in place, each one given as an `(offset, buffer)` pair.
+    ///
+    /// Offsets are in bytes.
+    /// Ranges are processed in order, and a key stream block shared by consecutive ranges is only computed once.
+    pub fn apply_keystream_sparse(
+        &self,
+        segments: &mut [(u64, &mut [u8])],
+    ) -> Result<(), &'static str> {
+        seekable::apply_keystream_sparse(self, segments)
+    }
+
+    /// Encrypt or decrypt `input` into `output`, given the offset.
+    ///
+    /// The offset is in bytes.
+    /// Both buffers must have the same length.
+    ///
+    /// This function is equivalent to copying `input` to `output` and then calling `apply_keystream` on `output`,
+    /// but the keystream is directly added to the input, so the memory is only traversed once.
+    pub fn apply_keystream_inout(
+        &self,
+        input: &[u8],
+        output: &mut [u8],
+        start_offset: u64,
+    ) -> Result<(), &'static str> {
+        seekable::apply_keystream_inout(self, input, output, start_offset)
+    }
+
+    /// Encrypt or decrypt an `InOutBuf`, given the offset.
+    ///
+    /// The offset is in bytes.
+    ///
+    /// This is the same as `apply_keystream_inout`, for code using the RustCrypto `inout` types,
+    /// that can represent both in-place and buffer-to-buffer operations.
+    #[cfg(feature = "inout")]
+    pub fn apply_keystream_inout_buf(
+        &self,
+        buf: inout::InOutBuf<'_, '_, u8>,
+        start_offset: u64,
+    ) -> Result<(), &'static str> {
+        seekable::apply_keystream_inout_buf(self, buf, start_offset)
+    }
+
+    /// Fill the given buffer with the keystream starting at the given 128-bit offset.
+    ///
+    /// The offset is in bytes, and can address the entire key stream, which is `2^64` blocks long.
+    pub fn fill_wide(&self, out: &mut [u8], start_offset: u128) -> Result<(), &'static str> {
+        seekable::fill_wide(self, out, start_offset)
+    }
+
+    /// Encrypt or decrypt the given buffer in place, given a 128-bit offset.
+    ///
+    /// The offset is in bytes, and can address the entire key stream, which is `2^64` blocks long.
+    /// This function is equivalent to calling `fill_wide` and then XORing the output with the input.
+    pub fn apply_keystream_wide(
+        &self,
+        out: &mut [u8],
+        start_offset: u128,
+    ) -> Result<(), &'static str> {
+        seekable::apply_keystream_wide(self, out, start_offset)
+    }
+
+    /// Return the key stream block at the given block index.
+    ///
+    /// Block `i` is the same as the `BLOCK_SIZE` bytes of the key stream at offset `i * BLOCK_SIZE`.
+    pub fn keystream_block(&self, block_offset: u64) -> [u8; Self::BLOCK_SIZE] {
+        self.squeeze_rate(block_offset)
+    }
+
+    /// Return an iterator over the bytes of the key stream, starting at the given offset.
+    ///
+    /// The iterator ends at offset `u64::MAX`.
+    pub fn keystream_bytes(&self, start_offset: u64) -> KeystreamBytes {
+        KeystreamBytes::new(*self, start_offset)
+    }
+
+    /// Return an iterator over `len` bytes of the key stream, starting at the given offset.
+    ///
+    /// The range is truncated at offset `u64::MAX`.
+    pub fn keystream_bytes_range(&self, start_offset: u64, len: usize) -> Bounded<KeystreamBytes> {
+        KeystreamBytes::new_range(*self, start_offset, len)
+    }
+
+    /// Return an iterator over the key stream blocks, starting at the given block index.
+    ///
+    /// The iterator ends after block `u64::MAX`.
+    pub fn keystream_blocks(&self, first_block: u64) -> KeystreamBlocks {
+        KeystreamBlocks::new(*self, first_block)
+    }
+
+    /// Return an iterator over `count` key stream blocks, starting at the given block index.
+    ///
+    /// The range is truncated after block `u64::MAX`.
+    pub fn keystream_blocks_range(
+        &self,
+        first_block: u64,
+        count: usize,
+    ) -> Bounded<KeystreamBlocks> {
+        KeystreamBlocks::new_range(*self, first_block, count)
+    }
+
+    /// Fill the given buffer with the keystream starting at the beginning of the given block.
+    ///
+    /// The buffer length doesn't have to be a multiple of the block size.
+    pub fn fill_blocks(&self, out: &mut [u8], first_block: u64) -> Result<(), &'static str> {
+        seekable::fill_blocks(self, out, first_block)
+    }
+
+    /// Encrypt or decrypt the given buffer in place, starting at the beginning of the given block.
+    ///
+    /// The buffer length doesn't have to be a multiple of the block size.
+    /// This function is equivalent to calling `fill_blocks` and then XORing the output with the input.
+    pub fn apply_blocks(&self, out: &mut [u8], first_block: u64) -> Result<(), &'static str> {
+        seekable::apply_blocks(self, out, first_block)
     }
 
     #[inline(always)]
-    fn round(&mut self, rk: u64) {
+    const fn round(&mut self, rk: u64) {
         let x = &mut self.st;
         x[2] ^= rk;
 
@@ -207,13 +448,213 @@ impl StreamCipher {
         x[2] = !x[2];
     }
 
-    fn permute(&mut self) {
-        for &rk in &Self::RKS {
-            self.round(rk);
+    const fn permute(&mut self) {
+        let mut i = 0;
+        while i < Self::RKS.len() {
+            self.round(Self::RKS[i]);
+            i += 1;
+        }
+    }
+}
+
+impl KeystreamBlock for StreamCipher {
+    const BLOCK_SIZE: usize = StreamCipher::BLOCK_SIZE;
+    type Block = [u8; StreamCipher::BLOCK_SIZE];
+
+    fn block(&self, index: u64) -> Self::Block {
+        self.squeeze_rate(index)
+    }
+
+    fn store_block(&self, out: &mut [u8], index: u64) {
+        self.store_rate(out, index);
+    }
+
+    fn xor_block(&self, out: &mut [u8], index: u64) {
+        self.apply_rate(out, index);
+    }
+
+    fn xor_block_inout(&self, input: &[u8], output: &mut [u8], index: u64) {
+        self.apply_rate_inout(input, output, index);
+    }
+}
+
+/// An iterator over the bytes of the key stream.
+pub type KeystreamBytes = seekable::KeystreamBytes<StreamCipher>;
+
+/// An iterator over the key stream blocks.
+pub type KeystreamBlocks = seekable::KeystreamBlocks<StreamCipher>;
+
+/// An ASCON state with a key already absorbed.
+///
+/// It can be used to efficiently create stream ciphers for different contexts with the same key.
+/// `KeyedBase::new(key).with_context(context)` is equivalent to `StreamCipher::new(key, context)`.
+#[derive(Clone, Copy)]
+pub struct KeyedBase {
+    /// The ASCON state after the key has been absorbed
+    st: [u64; 5],
+    /// The key
+    key: [u64; 4],
+}
+
+impl KeyedBase {
+    /// Absorb the given key.
+    ///
+    /// The key must be 32 bytes long, and must be randomly generated.
+    pub const fn new(key: &[u8; StreamCipher::KEY_LENGTH]) -> Self {
+        let key = [
+            crate::load_u64_le(key, 0),
+            crate::load_u64_le(key, 8),
+            crate::load_u64_le(key, 16),
+            crate::load_u64_le(key, 24),
+        ];
+        let st = [0x010080cc00000000, 0, 0, 0, 0];
+
+        let mut state = StreamCipher { st };
+        state.st[1] ^= key[0];
+        state.st[2] ^= key[1];
+        state.st[3] ^= key[2];
+        state.st[4] ^= key[3];
+        state.permute();
+
+        KeyedBase { st: state.st, key }
+    }
+
+    /// Create a new stream cipher for the given context.
+    ///
+    /// The context is optional can be of any length. It is used to improve multi-user security.
+    pub fn with_context(&self, context: impl AsRef<[u8]>) -> StreamCipher {
+        let mut builder = self.context_builder();
+        builder.absorb(context.as_ref());
+        builder.finish()
+    }
+
+    /// Start building a context made of multiple parts.
+    pub const fn context_builder(&self) -> ContextBuilder {
+        ContextBuilder {
+            state: StreamCipher { st: self.st },
+            key: self.key,
+            buf: [0u8; 32],
+            buf_len: 0,
         }
     }
 }
 
+/// An incremental builder for contexts made of multiple parts.
+///
+/// Contexts are often naturally tuples, such as `(tenant, bucket, object, version)`.
+/// Parts are absorbed one by one, without any allocations, and every part is prefixed with a
+/// one-byte type tag and its length encoded as a 64-bit little-endian integer, so that distinct
+/// tuples always map to distinct contexts, even when they mix byte strings and integers.
+///
+/// `ContextBuilder::new(key).part(a).part(b).finish()` is equivalent to
+/// `StreamCipher::new(key, context)` with `context = 0x00 || len(a) || a || 0x00 || len(b) || b`.
+/// Integers added with `part_u64()` use the `0x01` tag.
+#[derive(Clone, Copy)]
+pub struct ContextBuilder {
+    /// The ASCON state
+    state: StreamCipher,
+    /// The key
+    key: [u64; 4],
+    /// The pending, not yet absorbed context bytes
+    buf: [u8; 32],
+    /// The number of pending bytes
+    buf_len: usize,
+}
+
+impl ContextBuilder {
+    /// Start building a context for the given key.
+    ///
+    /// The key must be 32 bytes long, and must be randomly generated.
+    pub const fn new(key: &[u8; StreamCipher::KEY_LENGTH]) -> Self {
+        KeyedBase::new(key).context_builder()
+    }
+
+    /// The type tag of byte string parts
+    const TAG_BYTES: u8 = 0x00;
+
+    /// The type tag of integer parts
+    const TAG_U64: u8 = 0x01;
+
+    /// Add a part to the context.
+    pub fn part(self, part: impl AsRef<[u8]>) -> Self {
+        self.typed_part(Self::TAG_BYTES, part.as_ref())
+    }
+
+    /// Add an integer to the context, as an 8-byte little-endian part.
+    ///
+    /// Integer parts are tagged, and never encode like byte string parts.
+    pub fn part_u64(self, part: u64) -> Self {
+        self.typed_part(Self::TAG_U64, &part.to_le_bytes())
+    }
+
+    /// Add a part with the given type tag.
+    fn typed_part(mut self, tag: u8, part: &[u8]) -> Self {
+        self.absorb(&[tag]);
+        self.absorb(&(part.len() as u64).to_le_bytes());
+        self.absorb(part);
+        self
+    }
+
+    /// Create a new stream cipher for the context.
+    pub const fn finish(mut self) -> StreamCipher {
+        self.xor_buf();
+        self.state.st[4] ^= 0x01;
+        self.state.permute();
+
+        self.state.st[0] ^= self.key[0];
+        self.state.st[1] ^= self.key[1];
+        self.state.st[2] ^= self.key[2];
+        self.state.st[3] ^= self.key[3];
+
+        self.state
+    }
+
+    /// Absorb raw context bytes.
+    ///
+    /// A full block is only permuted once more data is added, as the last block is finalized by `finish()`.
+    fn absorb(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.buf_len == 32 {
+                self.xor_buf();
+                self.state.permute();
+                self.buf = [0u8; 32];
+                self.buf_len = 0;
+            }
+            let bytes_to_copy = cmp::min(32 - self.buf_len, data.len());
+            self.buf[self.buf_len..][..bytes_to_copy].copy_from_slice(&data[..bytes_to_copy]);
+            self.buf_len += bytes_to_copy;
+            data = &data[bytes_to_copy..];
+        }
+    }
+
+    /// Absorb raw context bytes, in a `const` context.
+    ///
+    /// This is equivalent to `absorb()`, one byte at a time.
+    const fn absorb_const(&mut self, data: &[u8]) {
+        let mut i = 0;
+        while i < data.len() {
+            if self.buf_len == 32 {
+                self.xor_buf();
+                self.state.permute();
+                self.buf = [0u8; 32];
+                self.buf_len = 0;
+            }
+            self.buf[self.buf_len] = data[i];
+            self.buf_len += 1;
+            i += 1;
+        }
+    }
+
+    /// Add the pending bytes to the state.
+    const fn xor_buf(&mut self) {
+        let buf = &self.buf;
+        self.state.st[0] ^= crate::load_u64_le(buf, 0);
+        self.state.st[1] ^= crate::load_u64_le(buf, 8);
+        self.state.st[2] ^= crate::load_u64_le(buf, 16);
+        self.state.st[3] ^= crate::load_u64_le(buf, 24);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -244,4 +685,316 @@ mod tests {
         let context = [0u8; 10000];
         let _ = StreamCipher::new(&key, context);
     }
+
+    #[test]
+    fn test_context_builder() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let st = ContextBuilder::new(&key)
+            .part(b"tenant")
+            .part_u64(42)
+            .part([0x42; 40])
+            .finish();
+
+        let mut context = [0u8; 9 + 6 + 9 + 8 + 9 + 40];
+        context[1] = 6;
+        context[9..15].copy_from_slice(b"tenant");
+        context[15] = 1;
+        context[16] = 8;
+        context[24] = 42;
+        context[33] = 40;
+        context[41..].fill(0x42);
+
+        let mut out = [0u8; 100];
+        st.fill(&mut out, 0).unwrap();
+        let mut out2 = [0u8; 100];
+        StreamCipher::new(&key, context).fill(&mut out2, 0).unwrap();
+        assert_eq!(out, out2);
+
+        let st2 = ContextBuilder::new(&key)
+            .part(b"ten")
+            .part(b"ant")
+            .part_u64(42)
+            .finish();
+        st2.fill(&mut out2, 0).unwrap();
+        assert!(out != out2);
+
+        let mut out3 = [0u8; 100];
+        ContextBuilder::new(&key)
+            .part_u64(42)
+            .finish()
+            .fill(&mut out2, 0)
+            .unwrap();
+        ContextBuilder::new(&key)
+            .part(42u64.to_le_bytes())
+            .finish()
+            .fill(&mut out3, 0)
+            .unwrap();
+        assert!(out2 != out3);
+    }
+
+    #[test]
+    fn test_keyed_base() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let base = KeyedBase::new(&key);
+        for context in [&b""[..], b"test", &[0x42; 100]] {
+            let mut out = [0u8; 100];
+            StreamCipher::new(&key, context).fill(&mut out, 0).unwrap();
+            let mut out2 = [0u8; 100];
+            base.with_context(context).fill(&mut out2, 0).unwrap();
+            assert_eq!(out, out2);
+        }
+    }
+
+    #[test]
+    fn test_blocks() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let st = StreamCipher::new(&key, b"test");
+
+        let mut out = [0u8; 1000];
+        st.fill(&mut out, 3 * 40).unwrap();
+        assert_eq!(st.keystream_block(4), out[40..2 * 40]);
+
+        let mut out2 = [0u8; 1000];
+        st.apply_blocks(&mut out2, 3).unwrap();
+        assert_eq!(out, out2);
+
+        st.fill_wide(&mut out2, 3 * 40).unwrap();
+        assert_eq!(out, out2);
+
+        let last = (40 << 64) - 40 - 10;
+        st.fill_wide(&mut out2[..40 + 10], last).unwrap();
+        assert_eq!(out2[10..40 + 10], st.keystream_block(u64::MAX));
+        assert!(st.fill_wide(&mut out2[..40 + 11], last).is_err());
+        assert!(st.apply_blocks(&mut out2[..40 + 1], u64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_inout() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let st = StreamCipher::new(&key, b"test");
+
+        let mut input = [0u8; 1000];
+        getrandom::fill(&mut input).unwrap();
+        for (start, len) in [(0, 1000), (3, 1), (5, 40), (40 - 1, 2 * 40 + 2)] {
+            let mut expected = [0u8; 1000];
+            expected[..len].copy_from_slice(&input[..len]);
+            st.apply_keystream(&mut expected[..len], start).unwrap();
+
+            let mut output = [0u8; 1000];
+            st.apply_keystream_inout(&input[..len], &mut output[..len], start)
+                .unwrap();
+            assert_eq!(output[..len], expected[..len]);
+        }
+        assert!(st
+            .apply_keystream_inout(&input[..10], &mut [0u8; 11], 0)
+            .is_err());
+    }
+
+    #[cfg(feature = "inout")]
+    #[test]
+    fn test_inout_buf() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let st = StreamCipher::new(&key, b"test");
+
+        let mut input = [0u8; 1000];
+        getrandom::fill(&mut input).unwrap();
+        let mut expected = [0u8; 1000];
+        st.apply_keystream_inout(&input, &mut expected, 7).unwrap();
+
+        let mut output = [0u8; 1000];
+        let buf = inout::InOutBuf::new(&input, &mut output).unwrap();
+        st.apply_keystream_inout_buf(buf, 7).unwrap();
+        assert_eq!(output, expected);
+
+        st.apply_keystream_inout_buf((&mut input[..]).into(), 7)
+            .unwrap();
+        assert_eq!(input, expected);
+    }
+
+    #[cfg(feature = "uninit")]
+    #[test]
+    fn test_fill_uninit() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let st = StreamCipher::new(&key, b"test");
+
+        let mut expected = [0u8; 1000];
+        st.fill(&mut expected, 13).unwrap();
+
+        let mut out = [core::mem::MaybeUninit::<u8>::uninit(); 1000];
+        let out = st.fill_uninit(&mut out, 13).unwrap();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_vectored() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let st = StreamCipher::new(&key, b"test");
+
+        let mut msg = [0u8; 1000];
+        getrandom::fill(&mut msg).unwrap();
+        let mut expected = msg;
+        st.apply_keystream(&mut expected, 5).unwrap();
+
+        let mut c = msg;
+        let (a, rest) = c.split_at_mut(3);
+        let (b, rest) = rest.split_at_mut(40);
+        let (d, e) = rest.split_at_mut(2 * 40 + 1);
+        st.apply_keystream_vectored(&mut [a, &mut [], b, d, e], 5)
+            .unwrap();
+        assert_eq!(c, expected);
+
+        let mut c = msg;
+        let (a, rest) = c.split_at_mut(10);
+        let (b, rest) = rest.split_at_mut(500);
+        let (d, e) = rest.split_at_mut(1);
+        st.apply_keystream_sparse(&mut [(515, d), (15, b), (516, e), (5, a)])
+            .unwrap();
+        assert_eq!(c, expected);
+        assert!(st
+            .apply_keystream_sparse(&mut [(u64::MAX, &mut [0u8; 2])])
+            .is_err());
+    }
+
+    #[test]
+    fn test_iterators() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let st = StreamCipher::new(&key, b"test");
+
+        let mut expected = [0u8; 1000];
+        st.fill(&mut expected, 100).unwrap();
+
+        let bytes = st.keystream_bytes_range(100, 1000);
+        assert_eq!(bytes.len(), 1000);
+        assert!(bytes.clone().eq(expected));
+        assert!(bytes.clone().rev().eq(expected.into_iter().rev()));
+        let mut bytes = st.keystream_bytes(100);
+        assert_eq!(bytes.nth(500), Some(expected[500]));
+        assert_eq!(bytes.next(), Some(expected[501]));
+
+        let mut blocks = st.keystream_blocks_range(3, 7);
+        assert_eq!(blocks.len(), 7);
+        assert_eq!(blocks.next(), Some(st.keystream_block(3)));
+        assert_eq!(blocks.nth_back(2), Some(st.keystream_block(7)));
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks.nth(10), None);
+        assert_eq!(st.keystream_blocks(u64::MAX - 1).count(), 2);
+        assert_eq!(
+            st.keystream_blocks(u64::MAX).next(),
+            Some(st.keystream_block(u64::MAX))
+        );
+    }
+
+    #[test]
+    fn test_fill_words() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let st = StreamCipher::new(&key, b"test");
+
+        let mut bytes = [0u8; 1000];
+        st.fill(&mut bytes, 24).unwrap();
+
+        let mut words64 = [0u64; 125];
+        st.fill_u64(&mut words64, 3).unwrap();
+        for (w, x) in words64.iter().zip(bytes.chunks_exact(8)) {
+            assert_eq!(*w, u64::from_le_bytes(x.try_into().unwrap()));
+        }
+
+        let mut words32 = [0u32; 250];
+        st.fill_u32(&mut words32, 6).unwrap();
+        for (w, x) in words32.iter().zip(bytes.chunks_exact(4)) {
+            assert_eq!(*w, u32::from_le_bytes(x.try_into().unwrap()));
+        }
+
+        assert!(st.fill_u64(&mut words64, u64::MAX / 8).is_err());
+    }
+
+    #[test]
+    fn test_const() {
+        const KEY: [u8; StreamCipher::KEY_LENGTH] = [0x42; StreamCipher::KEY_LENGTH];
+        const TABLE: [u8; 1000] = StreamCipher::new_const(&KEY, b"test").fill_const(7);
+
+        let mut expected = [0u8; 1000];
+        StreamCipher::new(&KEY, b"test")
+            .fill(&mut expected, 7)
+            .unwrap();
+        assert_eq!(TABLE, expected);
+
+        const LARGE: [u8; 100] = StreamCipher::new_const(&KEY, &[0x42; 500]).fill_const(0);
+        StreamCipher::new(&KEY, [0x42; 500])
+            .fill(&mut expected[..100], 0)
+            .unwrap();
+        assert_eq!(LARGE, expected[..100]);
+    }
+
+    #[test]
+    fn test_key_lengths() {
+        let mut key = [0u8; 64];
+        getrandom::fill(&mut key).unwrap();
+
+        let mut out = [0u8; 100];
+        let mut expected = [0u8; 100];
+        StreamCipher::new_with_key(&key[..32], b"test")
+            .unwrap()
+            .fill(&mut out, 0)
+            .unwrap();
+        StreamCipher::new(&key[..32].try_into().unwrap(), b"test")
+            .fill(&mut expected, 0)
+            .unwrap();
+        assert_eq!(out, expected);
+
+        let mut outputs = [[0u8; 100]; 4];
+        for (len, out) in [16, 31, 33, 64].into_iter().zip(outputs.iter_mut()) {
+            StreamCipher::new_with_key(&key[..len], b"test")
+                .unwrap()
+                .fill(out, 0)
+                .unwrap();
+            assert!(*out != expected);
+        }
+        for i in 1..outputs.len() {
+            assert!(outputs[i] != outputs[i - 1]);
+        }
+        assert!(StreamCipher::new_with_key(&key[..15], b"test").is_err());
+    }
+
+    #[cfg(feature = "hazmat")]
+    #[test]
+    fn test_state_bytes() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let st = StreamCipher::new(&key, b"test");
+        let bytes = st.to_state_bytes();
+        let st2 = StreamCipher::from_state_bytes(&bytes).unwrap();
+
+        let mut out = [0u8; 1000];
+        st.fill(&mut out, 5).unwrap();
+        let mut out2 = [0u8; 1000];
+        st2.fill(&mut out2, 5).unwrap();
+        assert_eq!(out, out2);
+
+        let mut bad = bytes;
+        bad[0] ^= 0xff;
+        assert!(StreamCipher::from_state_bytes(&bad).is_err());
+        let mut bad = bytes;
+        bad[1] ^= 0xff;
+        assert!(StreamCipher::from_state_bytes(&bad).is_err());
+        assert!(StreamCipher::from_state_bytes(&bytes[1..]).is_err());
+    }
 }
@@ -1,4 +1,6 @@
-use core::cmp;
+use crate::ascon;
+use crate::seekable::{self, Bounded, KeystreamBlock};
+use core::ops::DerefMut;
 
 /// An ChaCha-based seekable stream cipher.
 #[derive(Clone, Copy)]
@@ -11,37 +13,140 @@ impl StreamCipher {
     /// The key length in bytes
     pub const KEY_LENGTH: usize = 32;
 
+    /// The minimum length of a key passed to `new_with_key`, in bytes
+    pub const MIN_KEY_LENGTH: usize = 16;
+
+    /// The block size in bytes
+    pub const BLOCK_SIZE: usize = 64;
+
     /// The ChaCha constants
     const CONSTANTS: [u32; 4] = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
 
+    /// The constants used for key expansion
+    const EXPANSION_CONSTANTS: [u32; 4] = [0x61707865, 0x3120646e, 0x79622d36, 0x6b206574];
+
+    /// The length of an exported state, in bytes
+    #[cfg(feature = "hazmat")]
+    pub const STATE_BYTES_LENGTH: usize = 2 + 64;
+
+    /// The version of the exported state format
+    #[cfg(feature = "hazmat")]
+    const STATE_VERSION: u8 = 1;
+
     /// Create a new state with the given key and context.
     ///
     /// The key must be 32 bytes long, and must be randomly generated, for example using
     /// `rand::thread_rng().gen::<[u8; 32]>()` or `getrandom::fill()`.
     ///
     /// The context identifier is used to improve multi-user security.
-    pub fn new(key: &[u8; Self::KEY_LENGTH], id: &[u8; 8]) -> Self {
+    ///
+    /// This function can be used in a `const` context.
+    pub const fn new(key: &[u8; Self::KEY_LENGTH], id: &[u8; 8]) -> Self {
         let st = [
             Self::CONSTANTS[0],
             Self::CONSTANTS[1],
             Self::CONSTANTS[2],
             Self::CONSTANTS[3],
-            u32::from_le_bytes(key[0..4].try_into().unwrap()),
-            u32::from_le_bytes(key[4..8].try_into().unwrap()),
-            u32::from_le_bytes(key[8..12].try_into().unwrap()),
-            u32::from_le_bytes(key[12..16].try_into().unwrap()),
-            u32::from_le_bytes(key[16..20].try_into().unwrap()),
-            u32::from_le_bytes(key[20..24].try_into().unwrap()),
-            u32::from_le_bytes(key[24..28].try_into().unwrap()),
-            u32::from_le_bytes(key[28..32].try_into().unwrap()),
+            crate::load_u32_le(key, 0),
+            crate::load_u32_le(key, 4),
+            crate::load_u32_le(key, 8),
+            crate::load_u32_le(key, 12),
+            crate::load_u32_le(key, 16),
+            crate::load_u32_le(key, 20),
+            crate::load_u32_le(key, 24),
+            crate::load_u32_le(key, 28),
             0,
             0,
-            u32::from_le_bytes(id[0..4].try_into().unwrap()),
-            u32::from_le_bytes(id[4..8].try_into().unwrap()),
+            crate::load_u32_le(id, 0),
+            crate::load_u32_le(id, 4),
         ];
         StreamCipher { st }
     }
 
+    /// Create a new state with a key of arbitrary length, and the given context identifier.
+    ///
+    /// Keys of `KEY_LENGTH` bytes are used as-is, and this is then equivalent to `new`.
+    /// Other keys must be at least `MIN_KEY_LENGTH` bytes long. They are first expanded into
+    /// a `KEY_LENGTH`-byte key, using an HChaCha-like construction with distinct constants,
+    /// that processes the key in 32-byte chunks, along with its length.
+    ///
+    /// The security level is `8 * key.len()` bits for keys shorter than `KEY_LENGTH` bytes,
+    /// and 256 bits for longer keys.
+    pub fn new_with_key(key: &[u8], id: &[u8; 8]) -> Result<Self, &'static str> {
+        if let Ok(key) = key.try_into() {
+            return Ok(Self::new(key, id));
+        }
+        if key.len() < Self::MIN_KEY_LENGTH {
+            return Err("key is too short");
+        }
+        let mut expanded = [0u8; Self::KEY_LENGTH];
+        for (i, chunk) in key.chunks(Self::KEY_LENGTH).enumerate() {
+            for (k, x) in expanded.iter_mut().zip(chunk) {
+                *k ^= x;
+            }
+            expanded = Self::hchacha(&expanded, key.len() as u64, i as u64);
+        }
+        let st = Self::new(&expanded, id);
+        crate::zeroize(&mut expanded);
+        Ok(st)
+    }
+
+    /// Derive a key from a key, a key length and a chunk index.
+    fn hchacha(key: &[u8; Self::KEY_LENGTH], key_length: u64, chunk: u64) -> [u8; 32] {
+        let mut st = Self::new(key, &chunk.to_le_bytes()).st;
+        st[..4].copy_from_slice(&Self::EXPANSION_CONSTANTS);
+        st[12] = key_length as _;
+        st[13] = (key_length >> 32) as _;
+        for _ in 0..12 / 2 {
+            Self::double_round(&mut st);
+        }
+        let mut out = [0u8; 32];
+        for (i, x) in st[..4].iter().chain(&st[12..]).enumerate() {
+            out[i * 4..][..4].copy_from_slice(&x.to_le_bytes());
+        }
+        out
+    }
+
+    /// Export the state, so that it can be cached and later restored with `from_state_bytes`.
+    ///
+    /// The serialized state includes an algorithm identifier and a format version.
+    ///
+    /// # Security
+    ///
+    /// The state is as sensitive as the key: it can be used to compute the key stream.
+    /// It must be stored and erased with the same care as the key.
+    #[cfg(feature = "hazmat")]
+    pub fn to_state_bytes(&self) -> [u8; Self::STATE_BYTES_LENGTH] {
+        let mut out = [0u8; Self::STATE_BYTES_LENGTH];
+        out[0] = crate::any::Algorithm::ChaCha.code();
+        out[1] = Self::STATE_VERSION;
+        for (i, x) in self.st.iter().enumerate() {
+            out[2 + i * 4..][..4].copy_from_slice(&x.to_le_bytes());
+        }
+        out
+    }
+
+    /// Restore a state previously exported with `to_state_bytes`.
+    ///
+    /// The algorithm identifier and the format version are verified.
+    #[cfg(feature = "hazmat")]
+    pub fn from_state_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        if bytes.len() != Self::STATE_BYTES_LENGTH {
+            return Err("invalid state length");
+        }
+        if bytes[0] != crate::any::Algorithm::ChaCha.code() {
+            return Err("state was exported from a different algorithm");
+        }
+        if bytes[1] != Self::STATE_VERSION {
+            return Err("unsupported state version");
+        }
+        let mut st = [0u32; 16];
+        for (i, x) in st.iter_mut().enumerate() {
+            *x = crate::load_u32_le(bytes, 2 + i * 4);
+        }
+        Ok(StreamCipher { st })
+    }
+
     /// Squeeze a 32-byte block, and store it in the given buffer.
     #[inline(always)]
     fn store_rate(mut self, out: &mut [u8], block_offset: u64) {
@@ -55,6 +160,7 @@ impl StreamCipher {
 
     /// Squeeze a 32-byte block, and add it to the given buffer.
     #[inline(always)]
+    #[allow(clippy::identity_op, clippy::erasing_op)]
     fn apply_rate(mut self, out: &mut [u8], block_offset: u64) {
         self.st[12] = block_offset as _;
         self.st[13] = (block_offset >> 32) as _;
@@ -94,6 +200,18 @@ impl StreamCipher {
         out[15 * 4..][0..4].copy_from_slice(&(out15 ^ self.st[15]).to_le_bytes());
     }
 
+    /// Squeeze a 64-byte block, add it to the input, and store the result in the output buffer.
+    #[inline(always)]
+    fn apply_rate_inout(mut self, input: &[u8], output: &mut [u8], block_offset: u64) {
+        self.st[12] = block_offset as _;
+        self.st[13] = (block_offset >> 32) as _;
+        self.permute();
+        for i in 0..16 {
+            let x = u32::from_le_bytes(input[i * 4..][0..4].try_into().unwrap());
+            output[i * 4..][0..4].copy_from_slice(&(x ^ self.st[i]).to_le_bytes());
+        }
+    }
+
     /// Squeeze and return a 64-byte block.
     #[inline(always)]
     fn squeeze_rate(self, block_offset: u64) -> [u8; 64] {
@@ -102,34 +220,92 @@ impl StreamCipher {
         out
     }
 
+    /// Squeeze and return a block as 16 words.
+    #[inline(always)]
+    const fn squeeze_words(mut self, block_offset: u64) -> [u32; 16] {
+        self.st[12] = block_offset as _;
+        self.st[13] = (block_offset >> 32) as _;
+        self.permute();
+        self.st
+    }
+
     /// Fill the given buffer with the keystream starting at the given offset.
     ///
     /// The offset is in bytes.
     ///
     /// The key stream is deterministic: the same key, context and offset will always produce the same output.
-    pub fn fill(&self, mut out: &mut [u8], start_offset: u64) -> Result<(), &'static str> {
-        if start_offset.checked_add(out.len() as u64).is_none() {
-            return Err("offset would overflow");
-        }
-        let mut block_offset = start_offset / 64;
-        let offset_in_first_block = (start_offset % 64) as usize;
-        let bytes_to_copy = cmp::min(64 - offset_in_first_block, out.len());
-        if bytes_to_copy > 0 {
-            let rate = self.squeeze_rate(block_offset);
-            out[..bytes_to_copy].copy_from_slice(&rate[offset_in_first_block..][..bytes_to_copy]);
-            out = &mut out[bytes_to_copy..];
-        }
-        while out.len() >= 64 {
-            block_offset += 1;
-            self.store_rate(&mut out[..64], block_offset);
-            out = &mut out[64..];
-        }
-        if !out.is_empty() {
-            block_offset += 1;
-            let rate = self.squeeze_rate(block_offset);
-            out.copy_from_slice(&rate[..out.len()]);
+    pub fn fill(&self, out: &mut [u8], start_offset: u64) -> Result<(), &'static str> {
+        seekable::fill(self, out, start_offset)
+    }
+
+    /// Return `N` bytes of the keystream starting at the given offset, in a `const` context.
+    ///
+    /// The offset is in bytes.
+    ///
+    /// This is equivalent to `fill`, and can be used to compute tables at compile time.
+    /// Panics if the offset would overflow.
+    pub const fn fill_const<const N: usize>(&self, start_offset: u64) -> [u8; N] {
+        assert!(
+            start_offset.checked_add(N as u64).is_some(),
+            "offset would overflow"
+        );
+        let mut out = [0u8; N];
+        let mut i = 0;
+        while i < N {
+            let offset = start_offset + i as u64;
+            let words = self.squeeze_words(offset / Self::BLOCK_SIZE as u64);
+            let mut j = (offset % Self::BLOCK_SIZE as u64) as usize;
+            while j < Self::BLOCK_SIZE && i < N {
+                out[i] = (words[j / 4] >> (8 * (j % 4))) as u8;
+                i += 1;
+                j += 1;
+            }
         }
-        Ok(())
+        out
+    }
+
+    /// Fill the given buffer with 32-bit words of the keystream, starting at the given word offset.
+    ///
+    /// The offset is in 32-bit words.
+    ///
+    /// Word `i` is the little-endian interpretation of the 4 bytes of the key stream at offset `4 * i`,
+    /// so this is equivalent to calling `fill` with a byte offset of `4 * word_offset` and decoding the output.
+    pub fn fill_u32(&self, out: &mut [u32], word_offset: u64) -> Result<(), &'static str> {
+        seekable::fill_words(self, out, word_offset, |st, block_offset| {
+            st.squeeze_words(block_offset)
+        })
+    }
+
+    /// Fill the given buffer with 64-bit words of the keystream, starting at the given word offset.
+    ///
+    /// The offset is in 64-bit words.
+    ///
+    /// Word `i` is the little-endian interpretation of the 8 bytes of the key stream at offset `8 * i`,
+    /// so this is equivalent to calling `fill` with a byte offset of `8 * word_offset` and decoding the output.
+    pub fn fill_u64(&self, out: &mut [u64], word_offset: u64) -> Result<(), &'static str> {
+        seekable::fill_words(self, out, word_offset, |st, block_offset| {
+            let words = st.squeeze_words(block_offset);
+            let mut pairs = [0u64; 8];
+            for (i, pair) in pairs.iter_mut().enumerate() {
+                *pair = words[i * 2] as u64 | (words[i * 2 + 1] as u64) << 32;
+            }
+            pairs
+        })
+    }
+
+    /// Fill uninitialized memory with the keystream starting at the given offset.
+    ///
+    /// The offset is in bytes.
+    ///
+    /// This is the same as `fill`, but the buffer doesn't have to be initialized first.
+    /// The initialized buffer is returned.
+    #[cfg(feature = "uninit")]
+    pub fn fill_uninit<'a>(
+        &self,
+        out: &'a mut [core::mem::MaybeUninit<u8>],
+        start_offset: u64,
+    ) -> Result<&'a mut [u8], &'static str> {
+        seekable::fill_uninit(self, out, start_offset)
     }
 
     /// Encrypt or decrypt the given buffer in place, given the offset.
@@ -144,140 +320,328 @@ impl StreamCipher {
     ///
     /// * There is no integrity.
     /// * An adversary can flip arbitrary bits in the ciphertext and the corresponding bits in the plaintext will be flipped when decrypted.
-    pub fn apply_keystream(
+    pub fn apply_keystream(&self, out: &mut [u8], start_offset: u64) -> Result<(), &'static str> {
+        seekable::apply_keystream(self, out, start_offset)
+    }
+
+    /// Encrypt or decrypt a list of buffers in place, as if they were a single contiguous buffer.
+    ///
+    /// The offset is in bytes, and is the offset of the first buffer.
+    /// Any type dereferencing to a byte slice can be used, including `std::io::IoSliceMut`.
+    ///
+    /// A key stream block shared by adjacent buffers is only computed once.
+    pub fn apply_keystream_vectored<B: DerefMut<Target = [u8]>>(
         &self,
-        mut out: &mut [u8],
+        bufs: &mut [B],
         start_offset: u64,
     ) -> Result<(), &'static str> {
-        if start_offset.checked_add(out.len() as u64).is_none() {
-            return Err("offset would overflow");
+        seekable::apply_keystream_vectored(self, bufs, start_offset)
+    }
+
+    /// Encrypt or decrypt disjoint ranges in place, each one given as an `(offset, buffer)` pair.
+    ///
+    /// Offsets are in bytes.
+    /// Ranges are processed in order, and a key stream block shared by consecutive ranges is only computed once.
+    pub fn apply_keystream_sparse(
+        &self,
+        segments: &mut [(u64, &mut [u8])],
+    ) -> Result<(), &'static str> {
+        seekable::apply_keystream_sparse(self, segments)
+    }
+
+    /// Encrypt or decrypt `input` into `output`, given the offset.
+    ///
+    /// The offset is in bytes.
+    /// Both buffers must have the same length.
+    ///
+    /// This function is equivalent to copying `input` to `output` and then calling `apply_keystream` on `output`,
+    /// but the keystream is directly added to the input, so the memory is only traversed once.
+    pub fn apply_keystream_inout(
+        &self,
+        input: &[u8],
+        output: &mut [u8],
+        start_offset: u64,
+    ) -> Result<(), &'static str> {
+        seekable::apply_keystream_inout(self, input, output, start_offset)
+    }
+
+    /// Encrypt or decrypt an `InOutBuf`, given the offset.
+    ///
+    /// The offset is in bytes.
+    ///
+    /// This is the same as `apply_keystream_inout`, for code using the RustCrypto `inout` types,
+    /// that can represent both in-place and buffer-to-buffer operations.
+    #[cfg(feature = "inout")]
+    pub fn apply_keystream_inout_buf(
+        &self,
+        buf: inout::InOutBuf<'_, '_, u8>,
+        start_offset: u64,
+    ) -> Result<(), &'static str> {
+        seekable::apply_keystream_inout_buf(self, buf, start_offset)
+    }
+
+    /// Fill the given buffer with the keystream starting at the given 128-bit offset.
+    ///
+    /// The offset is in bytes, and can address the entire key stream, which is `2^64` blocks long.
+    pub fn fill_wide(&self, out: &mut [u8], start_offset: u128) -> Result<(), &'static str> {
+        seekable::fill_wide(self, out, start_offset)
+    }
+
+    /// Encrypt or decrypt the given buffer in place, given a 128-bit offset.
+    ///
+    /// The offset is in bytes, and can address the entire key stream, which is `2^64` blocks long.
+    /// This function is equivalent to calling `fill_wide` and then XORing the output with the input.
+    pub fn apply_keystream_wide(
+        &self,
+        out: &mut [u8],
+        start_offset: u128,
+    ) -> Result<(), &'static str> {
+        seekable::apply_keystream_wide(self, out, start_offset)
+    }
+
+    /// Return the key stream block at the given block index.
+    ///
+    /// Block `i` is the same as the `BLOCK_SIZE` bytes of the key stream at offset `i * BLOCK_SIZE`.
+    pub fn keystream_block(&self, block_offset: u64) -> [u8; Self::BLOCK_SIZE] {
+        self.squeeze_rate(block_offset)
+    }
+
+    /// Return an iterator over the bytes of the key stream, starting at the given offset.
+    ///
+    /// The iterator ends at offset `u64::MAX`.
+    pub fn keystream_bytes(&self, start_offset: u64) -> KeystreamBytes {
+        KeystreamBytes::new(*self, start_offset)
+    }
+
+    /// Return an iterator over `len` bytes of the key stream, starting at the given offset.
+    ///
+    /// The range is truncated at offset `u64::MAX`.
+    pub fn keystream_bytes_range(&self, start_offset: u64, len: usize) -> Bounded<KeystreamBytes> {
+        KeystreamBytes::new_range(*self, start_offset, len)
+    }
+
+    /// Return an iterator over the key stream blocks, starting at the given block index.
+    ///
+    /// The iterator ends after block `u64::MAX`.
+    pub fn keystream_blocks(&self, first_block: u64) -> KeystreamBlocks {
+        KeystreamBlocks::new(*self, first_block)
+    }
+
+    /// Return an iterator over `count` key stream blocks, starting at the given block index.
+    ///
+    /// The range is truncated after block `u64::MAX`.
+    pub fn keystream_blocks_range(
+        &self,
+        first_block: u64,
+        count: usize,
+    ) -> Bounded<KeystreamBlocks> {
+        KeystreamBlocks::new_range(*self, first_block, count)
+    }
+
+    /// Fill the given buffer with the keystream starting at the beginning of the given block.
+    ///
+    /// The buffer length doesn't have to be a multiple of the block size.
+    pub fn fill_blocks(&self, out: &mut [u8], first_block: u64) -> Result<(), &'static str> {
+        seekable::fill_blocks(self, out, first_block)
+    }
+
+    /// Encrypt or decrypt the given buffer in place, starting at the beginning of the given block.
+    ///
+    /// The buffer length doesn't have to be a multiple of the block size.
+    /// This function is equivalent to calling `fill_blocks` and then XORing the output with the input.
+    pub fn apply_blocks(&self, out: &mut [u8], first_block: u64) -> Result<(), &'static str> {
+        seekable::apply_blocks(self, out, first_block)
+    }
+
+    #[inline(always)]
+    const fn double_round(x: &mut [u32; 16]) {
+        {
+            const R: [usize; 4] = [0, 4, 8, 12];
+            x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
+            x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(16);
+            x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
+            x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(12);
+            x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
+            x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(8);
+            x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
+            x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(7);
         }
-        let mut block_offset = start_offset / 64;
-        let offset_in_first_block = (start_offset % 64) as usize;
-        let bytes_to_copy = cmp::min(64 - offset_in_first_block, out.len());
-        if bytes_to_copy > 0 {
-            let rate = self.squeeze_rate(block_offset);
-            for i in 0..bytes_to_copy {
-                out[i] ^= rate[offset_in_first_block + i];
-            }
-            out = &mut out[bytes_to_copy..];
+        {
+            const R: [usize; 4] = [1, 5, 9, 13];
+            x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
+            x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(16);
+            x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
+            x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(12);
+            x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
+            x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(8);
+            x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
+            x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(7);
         }
-        while out.len() >= 64 {
-            block_offset += 1;
-            self.apply_rate(&mut out[..64], block_offset);
-            out = &mut out[64..];
+        {
+            const R: [usize; 4] = [2, 6, 10, 14];
+            x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
+            x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(16);
+            x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
+            x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(12);
+            x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
+            x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(8);
+            x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
+            x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(7);
         }
-        if !out.is_empty() {
-            block_offset += 1;
-            let rate = self.squeeze_rate(block_offset);
-            for i in 0..out.len() {
-                out[i] ^= rate[i];
-            }
+        {
+            const R: [usize; 4] = [3, 7, 11, 15];
+            x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
+            x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(16);
+            x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
+            x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(12);
+            x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
+            x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(8);
+            x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
+            x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(7);
+        }
+        {
+            const R: [usize; 4] = [0, 5, 10, 15];
+            x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
+            x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(16);
+            x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
+            x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(12);
+            x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
+            x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(8);
+            x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
+            x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(7);
+        }
+        {
+            const R: [usize; 4] = [1, 6, 11, 12];
+            x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
+            x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(16);
+            x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
+            x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(12);
+            x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
+            x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(8);
+            x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
+            x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(7);
+        }
+        {
+            const R: [usize; 4] = [2, 7, 8, 13];
+            x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
+            x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(16);
+            x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
+            x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(12);
+            x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
+            x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(8);
+            x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
+            x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(7);
+        }
+        {
+            const R: [usize; 4] = [3, 4, 9, 14];
+            x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
+            x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(16);
+            x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
+            x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(12);
+            x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
+            x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(8);
+            x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
+            x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(7);
         }
-        Ok(())
     }
 
-    fn permute(&mut self) {
-        let mask: [u32; 12] = self.st[4..].try_into().unwrap();
-        let x = &mut self.st;
-        for _ in 0..12 / 2 {
-            {
-                const R: [usize; 4] = [0, 4, 8, 12];
-                x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
-                x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(16);
-                x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
-                x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(12);
-                x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
-                x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(8);
-                x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
-                x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(7);
-            }
-            {
-                const R: [usize; 4] = [1, 5, 9, 13];
-                x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
-                x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(16);
-                x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
-                x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(12);
-                x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
-                x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(8);
-                x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
-                x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(7);
-            }
-            {
-                const R: [usize; 4] = [2, 6, 10, 14];
-                x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
-                x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(16);
-                x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
-                x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(12);
-                x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
-                x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(8);
-                x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
-                x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(7);
-            }
-            {
-                const R: [usize; 4] = [3, 7, 11, 15];
-                x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
-                x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(16);
-                x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
-                x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(12);
-                x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
-                x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(8);
-                x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
-                x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(7);
-            }
-            {
-                const R: [usize; 4] = [0, 5, 10, 15];
-                x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
-                x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(16);
-                x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
-                x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(12);
-                x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
-                x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(8);
-                x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
-                x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(7);
-            }
-            {
-                const R: [usize; 4] = [1, 6, 11, 12];
-                x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
-                x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(16);
-                x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
-                x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(12);
-                x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
-                x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(8);
-                x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
-                x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(7);
-            }
-            {
-                const R: [usize; 4] = [2, 7, 8, 13];
-                x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
-                x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(16);
-                x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
-                x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(12);
-                x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
-                x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(8);
-                x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
-                x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(7);
-            }
-            {
-                const R: [usize; 4] = [3, 4, 9, 14];
-                x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
-                x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(16);
-                x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
-                x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(12);
-                x[R[0]] = x[R[0]].wrapping_add(x[R[1]]);
-                x[R[3]] = (x[R[3]] ^ x[R[0]]).rotate_left(8);
-                x[R[2]] = x[R[2]].wrapping_add(x[R[3]]);
-                x[R[1]] = (x[R[1]] ^ x[R[2]]).rotate_left(7);
-            }
+    const fn permute(&mut self) {
+        let mask = self.st;
+        let mut i = 0;
+        while i < 12 / 2 {
+            Self::double_round(&mut self.st);
+            i += 1;
         }
+        let x = &mut self.st;
         x[0] = x[0].wrapping_add(Self::CONSTANTS[0]);
         x[1] = x[1].wrapping_add(Self::CONSTANTS[1]);
         x[2] = x[2].wrapping_add(Self::CONSTANTS[2]);
         x[3] = x[3].wrapping_add(Self::CONSTANTS[3]);
-        for i in 4..16 {
-            x[i] = x[i].wrapping_add(mask[i - 4]);
+        let mut i = 4;
+        while i < 16 {
+            x[i] = x[i].wrapping_add(mask[i]);
+            i += 1;
+        }
+    }
+}
+
+impl KeystreamBlock for StreamCipher {
+    const BLOCK_SIZE: usize = StreamCipher::BLOCK_SIZE;
+    type Block = [u8; StreamCipher::BLOCK_SIZE];
+
+    fn block(&self, index: u64) -> Self::Block {
+        self.squeeze_rate(index)
+    }
+
+    fn store_block(&self, out: &mut [u8], index: u64) {
+        self.store_rate(out, index);
+    }
+
+    fn xor_block(&self, out: &mut [u8], index: u64) {
+        self.apply_rate(out, index);
+    }
+
+    fn xor_block_inout(&self, input: &[u8], output: &mut [u8], index: u64) {
+        self.apply_rate_inout(input, output, index);
+    }
+}
+
+/// An iterator over the bytes of the key stream.
+pub type KeystreamBytes = seekable::KeystreamBytes<StreamCipher>;
+
+/// An iterator over the key stream blocks.
+pub type KeystreamBlocks = seekable::KeystreamBlocks<StreamCipher>;
+
+/// An incremental builder for context identifiers made of multiple parts.
+///
+/// ChaCha only accepts an 8-byte context identifier, but contexts are often naturally tuples,
+/// such as `(tenant, bucket, object, version)`. This hashes such tuples into identifiers, using
+/// the same unambiguous, typed and length-prefixed encoding as `ascon::ContextBuilder`, without any
+/// allocations.
+///
+/// Identifiers are 64-bit long, so collisions are expected after about `2^32` distinct tuples.
+#[derive(Clone, Copy)]
+pub struct IdBuilder {
+    /// The underlying hash function
+    builder: ascon::ContextBuilder,
+}
+
+impl IdBuilder {
+    /// Start building a context identifier.
+    pub fn new() -> Self {
+        let builder = ascon::ContextBuilder::new(&[0u8; ascon::StreamCipher::KEY_LENGTH])
+            .part(b"seekable-stream-cipher/chacha-id");
+        IdBuilder { builder }
+    }
+
+    /// Add a part to the identifier.
+    pub fn part(self, part: impl AsRef<[u8]>) -> Self {
+        IdBuilder {
+            builder: self.builder.part(part),
         }
     }
+
+    /// Add an integer to the identifier, as an 8-byte little-endian part.
+    ///
+    /// Integer parts are tagged, and never encode like byte string parts.
+    pub fn part_u64(self, part: u64) -> Self {
+        IdBuilder {
+            builder: self.builder.part_u64(part),
+        }
+    }
+
+    /// Return the context identifier.
+    pub fn finish(self) -> [u8; 8] {
+        let mut id = [0u8; 8];
+        self.builder.finish().fill(&mut id, 0).unwrap();
+        id
+    }
+}
+
+impl Default for IdBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[cfg(test)]
@@ -302,4 +666,268 @@ mod tests {
         st.fill(&mut out2, 11).unwrap();
         assert_eq!(out[1..], out2[0..out2.len() - 1]);
     }
+
+    #[test]
+    fn test_id_builder() {
+        let id = IdBuilder::new().part(b"tenant").part_u64(42).finish();
+        assert_eq!(id, IdBuilder::new().part(b"tenant").part_u64(42).finish());
+        assert!(
+            id != IdBuilder::new()
+                .part(b"tenan")
+                .part(b"t")
+                .part_u64(42)
+                .finish()
+        );
+        assert!(
+            IdBuilder::new().part_u64(42).finish()
+                != IdBuilder::new().part(42u64.to_le_bytes()).finish()
+        );
+
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+        let _ = StreamCipher::new(&key, &id);
+    }
+
+    #[test]
+    fn test_blocks() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let st = StreamCipher::new(&key, b"testtest");
+
+        let mut out = [0u8; 1000];
+        st.fill(&mut out, 3 * 64).unwrap();
+        assert_eq!(st.keystream_block(4), out[64..2 * 64]);
+
+        let mut out2 = [0u8; 1000];
+        st.apply_blocks(&mut out2, 3).unwrap();
+        assert_eq!(out, out2);
+
+        st.fill_wide(&mut out2, 3 * 64).unwrap();
+        assert_eq!(out, out2);
+
+        let last = (64 << 64) - 64 - 10;
+        st.fill_wide(&mut out2[..64 + 10], last).unwrap();
+        assert_eq!(out2[10..64 + 10], st.keystream_block(u64::MAX));
+        assert!(st.fill_wide(&mut out2[..64 + 11], last).is_err());
+        assert!(st.apply_blocks(&mut out2[..64 + 1], u64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_inout() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let st = StreamCipher::new(&key, b"testtest");
+
+        let mut input = [0u8; 1000];
+        getrandom::fill(&mut input).unwrap();
+        for (start, len) in [(0, 1000), (3, 1), (5, 64), (64 - 1, 2 * 64 + 2)] {
+            let mut expected = [0u8; 1000];
+            expected[..len].copy_from_slice(&input[..len]);
+            st.apply_keystream(&mut expected[..len], start).unwrap();
+
+            let mut output = [0u8; 1000];
+            st.apply_keystream_inout(&input[..len], &mut output[..len], start)
+                .unwrap();
+            assert_eq!(output[..len], expected[..len]);
+        }
+        assert!(st
+            .apply_keystream_inout(&input[..10], &mut [0u8; 11], 0)
+            .is_err());
+    }
+
+    #[cfg(feature = "inout")]
+    #[test]
+    fn test_inout_buf() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let st = StreamCipher::new(&key, b"testtest");
+
+        let mut input = [0u8; 1000];
+        getrandom::fill(&mut input).unwrap();
+        let mut expected = [0u8; 1000];
+        st.apply_keystream_inout(&input, &mut expected, 7).unwrap();
+
+        let mut output = [0u8; 1000];
+        let buf = inout::InOutBuf::new(&input, &mut output).unwrap();
+        st.apply_keystream_inout_buf(buf, 7).unwrap();
+        assert_eq!(output, expected);
+
+        st.apply_keystream_inout_buf((&mut input[..]).into(), 7)
+            .unwrap();
+        assert_eq!(input, expected);
+    }
+
+    #[cfg(feature = "uninit")]
+    #[test]
+    fn test_fill_uninit() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let st = StreamCipher::new(&key, b"testtest");
+
+        let mut expected = [0u8; 1000];
+        st.fill(&mut expected, 13).unwrap();
+
+        let mut out = [core::mem::MaybeUninit::<u8>::uninit(); 1000];
+        let out = st.fill_uninit(&mut out, 13).unwrap();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_vectored() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let st = StreamCipher::new(&key, b"testtest");
+
+        let mut msg = [0u8; 1000];
+        getrandom::fill(&mut msg).unwrap();
+        let mut expected = msg;
+        st.apply_keystream(&mut expected, 5).unwrap();
+
+        let mut c = msg;
+        let (a, rest) = c.split_at_mut(3);
+        let (b, rest) = rest.split_at_mut(64);
+        let (d, e) = rest.split_at_mut(2 * 64 + 1);
+        st.apply_keystream_vectored(&mut [a, &mut [], b, d, e], 5)
+            .unwrap();
+        assert_eq!(c, expected);
+
+        let mut c = msg;
+        let (a, rest) = c.split_at_mut(10);
+        let (b, rest) = rest.split_at_mut(500);
+        let (d, e) = rest.split_at_mut(1);
+        st.apply_keystream_sparse(&mut [(515, d), (15, b), (516, e), (5, a)])
+            .unwrap();
+        assert_eq!(c, expected);
+        assert!(st
+            .apply_keystream_sparse(&mut [(u64::MAX, &mut [0u8; 2])])
+            .is_err());
+    }
+
+    #[test]
+    fn test_iterators() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let st = StreamCipher::new(&key, b"testtest");
+
+        let mut expected = [0u8; 1000];
+        st.fill(&mut expected, 100).unwrap();
+
+        let bytes = st.keystream_bytes_range(100, 1000);
+        assert_eq!(bytes.len(), 1000);
+        assert!(bytes.clone().eq(expected));
+        assert!(bytes.clone().rev().eq(expected.into_iter().rev()));
+        let mut bytes = st.keystream_bytes(100);
+        assert_eq!(bytes.nth(500), Some(expected[500]));
+        assert_eq!(bytes.next(), Some(expected[501]));
+
+        let mut blocks = st.keystream_blocks_range(3, 7);
+        assert_eq!(blocks.len(), 7);
+        assert_eq!(blocks.next(), Some(st.keystream_block(3)));
+        assert_eq!(blocks.nth_back(2), Some(st.keystream_block(7)));
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks.nth(10), None);
+        assert_eq!(st.keystream_blocks(u64::MAX - 1).count(), 2);
+        assert_eq!(
+            st.keystream_blocks(u64::MAX).next(),
+            Some(st.keystream_block(u64::MAX))
+        );
+    }
+
+    #[test]
+    fn test_fill_words() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let st = StreamCipher::new(&key, b"testtest");
+
+        let mut bytes = [0u8; 1000];
+        st.fill(&mut bytes, 24).unwrap();
+
+        let mut words64 = [0u64; 125];
+        st.fill_u64(&mut words64, 3).unwrap();
+        for (w, x) in words64.iter().zip(bytes.chunks_exact(8)) {
+            assert_eq!(*w, u64::from_le_bytes(x.try_into().unwrap()));
+        }
+
+        let mut words32 = [0u32; 250];
+        st.fill_u32(&mut words32, 6).unwrap();
+        for (w, x) in words32.iter().zip(bytes.chunks_exact(4)) {
+            assert_eq!(*w, u32::from_le_bytes(x.try_into().unwrap()));
+        }
+
+        assert!(st.fill_u64(&mut words64, u64::MAX / 8).is_err());
+    }
+
+    #[test]
+    fn test_const() {
+        const KEY: [u8; StreamCipher::KEY_LENGTH] = [0x42; StreamCipher::KEY_LENGTH];
+        const TABLE: [u8; 1000] = StreamCipher::new(&KEY, b"testtest").fill_const(7);
+
+        let mut expected = [0u8; 1000];
+        StreamCipher::new(&KEY, b"testtest")
+            .fill(&mut expected, 7)
+            .unwrap();
+        assert_eq!(TABLE, expected);
+    }
+
+    #[test]
+    fn test_key_lengths() {
+        let mut key = [0u8; 64];
+        getrandom::fill(&mut key).unwrap();
+
+        let mut out = [0u8; 100];
+        let mut expected = [0u8; 100];
+        StreamCipher::new_with_key(&key[..32], b"testtest")
+            .unwrap()
+            .fill(&mut out, 0)
+            .unwrap();
+        StreamCipher::new(&key[..32].try_into().unwrap(), b"testtest")
+            .fill(&mut expected, 0)
+            .unwrap();
+        assert_eq!(out, expected);
+
+        let mut outputs = [[0u8; 100]; 4];
+        for (len, out) in [16, 31, 33, 64].into_iter().zip(outputs.iter_mut()) {
+            StreamCipher::new_with_key(&key[..len], b"testtest")
+                .unwrap()
+                .fill(out, 0)
+                .unwrap();
+            assert!(*out != expected);
+        }
+        for i in 1..outputs.len() {
+            assert!(outputs[i] != outputs[i - 1]);
+        }
+        assert!(StreamCipher::new_with_key(&key[..15], b"testtest").is_err());
+    }
+
+    #[cfg(feature = "hazmat")]
+    #[test]
+    fn test_state_bytes() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let st = StreamCipher::new(&key, b"testtest");
+        let bytes = st.to_state_bytes();
+        let st2 = StreamCipher::from_state_bytes(&bytes).unwrap();
+
+        let mut out = [0u8; 1000];
+        st.fill(&mut out, 5).unwrap();
+        let mut out2 = [0u8; 1000];
+        st2.fill(&mut out2, 5).unwrap();
+        assert_eq!(out, out2);
+
+        let mut bad = bytes;
+        bad[0] ^= 0xff;
+        assert!(StreamCipher::from_state_bytes(&bad).is_err());
+        let mut bad = bytes;
+        bad[1] ^= 0xff;
+        assert!(StreamCipher::from_state_bytes(&bad).is_err());
+        assert!(StreamCipher::from_state_bytes(&bytes[1..]).is_err());
+    }
 }
@@ -0,0 +1,157 @@
+use crate::any::{Algorithm, AnyStreamCipher};
+use crate::provider::KeyProvider;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+/// A serializable description of how an object is encrypted.
+///
+/// A descriptor can be stored in a manifest next to the encrypted object, and turned back into
+/// a stream cipher given the key. It never contains any key material, nor any cipher state.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CipherDescriptor {
+    /// The stream cipher algorithm
+    pub algorithm: Algorithm,
+    /// The number of rounds of the permutation
+    pub rounds: u32,
+    /// The context
+    pub context: Vec<u8>,
+    /// The size of the chunks the object is processed in, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_size: Option<u64>,
+    /// An identifier of the key, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<u64>,
+}
+
+impl CipherDescriptor {
+    /// The number of rounds used by all the stream ciphers
+    pub const ROUNDS: u32 = 12;
+
+    /// Create a descriptor for the given algorithm and context.
+    pub fn new(algorithm: Algorithm, context: impl AsRef<[u8]>) -> Self {
+        CipherDescriptor {
+            algorithm,
+            rounds: Self::ROUNDS,
+            context: context.as_ref().to_vec(),
+            chunk_size: None,
+            key_id: None,
+        }
+    }
+
+    /// Set the chunk size.
+    pub fn with_chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = Some(chunk_size);
+        self
+    }
+
+    /// Set the key identifier.
+    pub fn with_key_id(mut self, key_id: u64) -> Self {
+        self.key_id = Some(key_id);
+        self
+    }
+
+    /// Check that the parameters are supported.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.rounds != Self::ROUNDS {
+            return Err("unsupported number of rounds");
+        }
+        if self.chunk_size == Some(0) {
+            return Err("chunk size must not be zero");
+        }
+        Ok(())
+    }
+
+    /// Create the stream cipher described by this descriptor, given the key.
+    pub fn cipher(
+        &self,
+        key: &[u8; AnyStreamCipher::KEY_LENGTH],
+    ) -> Result<AnyStreamCipher, &'static str> {
+        self.validate()?;
+        Ok(AnyStreamCipher::new(self.algorithm, key, &self.context))
+    }
+
+    /// Create a stream cipher using the active key of a key provider, for new data.
+    ///
+    /// The ID of the key is recorded in the descriptor, so that `open()` can find the key again
+    /// after the active key has been rotated.
+    pub fn seal(
+        mut self,
+        provider: &impl KeyProvider,
+    ) -> Result<(Self, AnyStreamCipher), &'static str> {
+        let (key_id, key) = provider.active_key()?;
+        self.key_id = Some(key_id);
+        let st = self.cipher(&key)?;
+        Ok((self, st))
+    }
+
+    /// Create the stream cipher described by this descriptor, using the key recorded by `seal()`.
+    pub fn open(&self, provider: &impl KeyProvider) -> Result<AnyStreamCipher, &'static str> {
+        let key_id = self.key_id.ok_or("descriptor has no key ID")?;
+        let key = provider.key(key_id)?;
+        self.cipher(&key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_descriptor() {
+        let mut key = [0u8; AnyStreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let descriptor = CipherDescriptor::new(Algorithm::ChaCha, b"object 42")
+            .with_chunk_size(65536)
+            .with_key_id(7);
+        let json = serde_json::to_string(&descriptor).unwrap();
+        assert!(json.contains("\"algorithm\":\"chacha\""));
+        let descriptor2: CipherDescriptor = serde_json::from_str(&json).unwrap();
+        assert_eq!(descriptor, descriptor2);
+
+        let mut out = [0u8; 100];
+        descriptor2.cipher(&key).unwrap().fill(&mut out, 0).unwrap();
+        let mut expected = [0u8; 100];
+        AnyStreamCipher::new(Algorithm::ChaCha, &key, b"object 42")
+            .fill(&mut expected, 0)
+            .unwrap();
+        assert_eq!(out, expected);
+
+        let descriptor: CipherDescriptor =
+            serde_json::from_str(r#"{"algorithm":"ascon","rounds":8,"context":[1,2,3]}"#).unwrap();
+        assert!(descriptor.cipher(&key).is_err());
+        assert!(serde_json::from_str::<CipherDescriptor>(
+            r#"{"algorithm":"aes","rounds":12,"context":[]}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_descriptor_provider() {
+        use crate::key::Key;
+        use crate::provider::MemoryKeyProvider;
+
+        let mut provider = MemoryKeyProvider::new();
+        provider.rotate(Key::from([1u8; Key::LENGTH])).unwrap();
+
+        let (descriptor, st) = CipherDescriptor::new(Algorithm::Ascon, b"object 42")
+            .seal(&provider)
+            .unwrap();
+        assert_eq!(descriptor.key_id, Some(0));
+        let mut c = [0u8; 100];
+        st.apply_keystream(&mut c, 0).unwrap();
+
+        provider.rotate(Key::from([2u8; Key::LENGTH])).unwrap();
+        let json = serde_json::to_string(&descriptor).unwrap();
+        let descriptor: CipherDescriptor = serde_json::from_str(&json).unwrap();
+        descriptor
+            .open(&provider)
+            .unwrap()
+            .apply_keystream(&mut c, 0)
+            .unwrap();
+        assert_eq!(c, [0u8; 100]);
+        assert!(CipherDescriptor::new(Algorithm::Ascon, b"object 42")
+            .open(&provider)
+            .is_err());
+    }
+}
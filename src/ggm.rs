@@ -0,0 +1,460 @@
+use crate::ascon::StreamCipher;
+use crate::provider::KeyProvider;
+use core::cmp;
+
+/// A key for a subtree of a `KeyTree`.
+///
+/// It grants access to a contiguous, aligned range of the keystream, and nothing else.
+#[derive(Clone)]
+pub struct SubtreeKey {
+    /// The base-2 logarithm of the number of bytes covered by a leaf
+    leaf_bits: u8,
+    /// The height of the subtree, leaves being at height 0
+    height: u8,
+    /// The index of the subtree among the subtrees of the same height
+    index: u64,
+    /// The subtree key
+    key: [u8; StreamCipher::KEY_LENGTH],
+}
+
+impl SubtreeKey {
+    /// The length of a serialized subtree key in bytes
+    pub const LENGTH: usize = 2 + 8 + StreamCipher::KEY_LENGTH;
+
+    /// Return the range of bytes covered by this key, as `(start, end)` with `end` excluded.
+    pub fn range(&self) -> (u64, u128) {
+        let bits = self.leaf_bits as u32 + self.height as u32;
+        let start = (self.index as u128) << bits;
+        let end = (self.index as u128 + 1) << bits;
+        (start as u64, end)
+    }
+
+    /// Serialize the subtree key, to be sent to a delegate.
+    pub fn to_bytes(&self) -> [u8; Self::LENGTH] {
+        let mut out = [0u8; Self::LENGTH];
+        out[0] = self.leaf_bits;
+        out[1] = self.height;
+        out[2..10].copy_from_slice(&self.index.to_le_bytes());
+        out[10..].copy_from_slice(&self.key);
+        out
+    }
+
+    /// Deserialize a subtree key.
+    pub fn from_bytes(bytes: &[u8; Self::LENGTH]) -> Result<Self, &'static str> {
+        let key = SubtreeKey {
+            leaf_bits: bytes[0],
+            height: bytes[1],
+            index: u64::from_le_bytes(bytes[2..10].try_into().unwrap()),
+            key: bytes[10..].try_into().unwrap(),
+        };
+        key.validate()?;
+        Ok(key)
+    }
+
+    /// Check that the leaf size, the height and the index describe a subtree of a valid tree.
+    fn validate(&self) -> Result<(), &'static str> {
+        let leaf_bits = self.leaf_bits as u32;
+        let height = self.height as u32;
+        if !(KeyTree::MIN_LEAF_BITS..=KeyTree::MAX_LEAF_BITS).contains(&self.leaf_bits)
+            || height > 64 - leaf_bits
+            || (self.index as u128) >> (64 - leaf_bits - height) != 0
+        {
+            return Err("invalid subtree key");
+        }
+        Ok(())
+    }
+
+    /// Check if this key covers the given leaf.
+    fn covers(&self, leaf: u64) -> bool {
+        leaf >> self.height == self.index
+    }
+
+    /// Derive the key of a leaf from the subtree key.
+    fn leaf_key(&self, leaf: u64) -> [u8; StreamCipher::KEY_LENGTH] {
+        self.descendant_key(0, leaf)
+    }
+
+    /// Derive the key of a descendant subtree from the subtree key.
+    fn descendant_key(&self, height: u8, index: u64) -> [u8; StreamCipher::KEY_LENGTH] {
+        debug_assert!(height <= self.height && index >> (self.height - height) == self.index);
+        let mut key = self.key;
+        for level in (0..self.height - height).rev() {
+            let right = (index >> level) & 1 == 1;
+            let mut children = [0u8; 2 * StreamCipher::KEY_LENGTH];
+            StreamCipher::new(&key, b"ggm")
+                .fill(&mut children, 0)
+                .unwrap();
+            let child = if right {
+                &children[StreamCipher::KEY_LENGTH..]
+            } else {
+                &children[..StreamCipher::KEY_LENGTH]
+            };
+            key.copy_from_slice(child);
+            crate::zeroize(&mut children);
+        }
+        key
+    }
+}
+
+impl Drop for SubtreeKey {
+    fn drop(&mut self) {
+        crate::zeroize(&mut self.key);
+    }
+}
+
+/// A tree-based key derivation over the keystream, allowing range-restricted delegation.
+///
+/// The keystream is split into leaves of `2^leaf_bits` bytes, each one being encrypted using its
+/// own key. Leaf keys are derived from the root key using a GGM tree, whose pseudorandom generator
+/// is the Ascon-based stream cipher.
+///
+/// A `SubtreeKey` can derive the keys of the leaves below it, but not of any other leaf.
+/// `KeyTree::delegate()` computes the minimal set of subtree keys covering a byte range, which can
+/// then be used by a `RestrictedCipher` to encrypt or decrypt that range.
+///
+/// Deriving a leaf key requires one PRG evaluation per tree level, so leaves should be large
+/// enough for that cost to be amortized.
+///
+/// # Caveats
+///
+/// * There is no integrity.
+/// * Access is granted with a leaf granularity: a delegate can decrypt whole leaves even if the requested range only covers a part of them.
+#[derive(Clone)]
+pub struct KeyTree {
+    /// The root of the tree
+    root: SubtreeKey,
+}
+
+impl KeyTree {
+    /// The key length in bytes
+    pub const KEY_LENGTH: usize = StreamCipher::KEY_LENGTH;
+
+    /// The minimum base-2 logarithm of the leaf size
+    pub const MIN_LEAF_BITS: u8 = 6;
+
+    /// The maximum base-2 logarithm of the leaf size
+    pub const MAX_LEAF_BITS: u8 = 63;
+
+    /// The maximum number of subtree keys returned by `delegate()`
+    pub const MAX_DELEGATED_KEYS: usize = 2 * 64;
+
+    /// Create a new key tree with the given key, context and leaf size.
+    ///
+    /// The key must be 32 bytes long, and must be randomly generated.
+    ///
+    /// The context is optional can be of any length. It is used to improve multi-user security.
+    ///
+    /// Every leaf covers `2^leaf_bits` bytes of the keystream.
+    pub fn new(
+        key: &[u8; Self::KEY_LENGTH],
+        context: impl AsRef<[u8]>,
+        leaf_bits: u8,
+    ) -> Result<Self, &'static str> {
+        if !(Self::MIN_LEAF_BITS..=Self::MAX_LEAF_BITS).contains(&leaf_bits) {
+            return Err("unsupported leaf size");
+        }
+        let root_key = crate::derive_key(key, b"seekable-stream-cipher/ggm", context.as_ref());
+        let root = SubtreeKey {
+            leaf_bits,
+            height: 64 - leaf_bits,
+            index: 0,
+            key: root_key,
+        };
+        Ok(KeyTree { root })
+    }
+
+    /// Create a new key tree using the active key of a key provider.
+    ///
+    /// The ID of the key is returned along with the tree. A key tree has no state of its own,
+    /// so the ID must be stored with the encrypted data, for `new_with_key_id()` to find the
+    /// key again after the active key has been rotated.
+    pub fn new_with_active_key(
+        provider: &impl KeyProvider,
+        context: impl AsRef<[u8]>,
+        leaf_bits: u8,
+    ) -> Result<(u64, Self), &'static str> {
+        let (key_id, key) = provider.active_key()?;
+        Ok((key_id, Self::new(&key, context, leaf_bits)?))
+    }
+
+    /// Create a key tree for existing data, using the key with the given ID.
+    pub fn new_with_key_id(
+        provider: &impl KeyProvider,
+        key_id: u64,
+        context: impl AsRef<[u8]>,
+        leaf_bits: u8,
+    ) -> Result<Self, &'static str> {
+        let key = provider.key(key_id)?;
+        Self::new(&key, context, leaf_bits)
+    }
+
+    /// Compute the minimal set of subtree keys covering the given byte range.
+    ///
+    /// The range is `start..end`, and is extended to leaf boundaries.
+    /// The keys are derived as the returned iterator is consumed.
+    /// There are at most `MAX_DELEGATED_KEYS` of them.
+    pub fn delegate(&self, start: u64, end: u64) -> Result<Delegation<'_>, &'static str> {
+        if start >= end {
+            return Err("empty range");
+        }
+        let leaf_bits = self.root.leaf_bits as u32;
+        Ok(Delegation {
+            root: &self.root,
+            leaf: (start >> leaf_bits) as u128,
+            end_leaf: ((end as u128) + (1 << leaf_bits) - 1) >> leaf_bits,
+        })
+    }
+
+    /// Fill the given buffer with the keystream starting at the given offset.
+    ///
+    /// The offset is in bytes.
+    pub fn fill(&self, out: &mut [u8], start_offset: u64) -> Result<(), &'static str> {
+        process(core::slice::from_ref(&self.root), out, start_offset, false)
+    }
+
+    /// Encrypt or decrypt the given buffer in place, given the offset.
+    ///
+    /// The offset is in bytes.
+    pub fn apply_keystream(&self, out: &mut [u8], start_offset: u64) -> Result<(), &'static str> {
+        process(core::slice::from_ref(&self.root), out, start_offset, true)
+    }
+}
+
+/// An iterator over the subtree keys covering a range, returned by `KeyTree::delegate()`.
+pub struct Delegation<'a> {
+    /// The root of the tree
+    root: &'a SubtreeKey,
+    /// The first leaf that remains to be covered
+    leaf: u128,
+    /// The leaf following the range
+    end_leaf: u128,
+}
+
+impl Delegation<'_> {
+    /// Return the height and the index of the largest subtree starting at the given leaf
+    /// and contained in the range.
+    fn subtree(&self, leaf: u128) -> (u32, u64) {
+        let mut height = 0;
+        while height < self.root.height as u32
+            && leaf & ((2 << height) - 1) == 0
+            && leaf + (2 << height) <= self.end_leaf
+        {
+            height += 1;
+        }
+        (height, (leaf >> height) as u64)
+    }
+}
+
+impl Iterator for Delegation<'_> {
+    type Item = SubtreeKey;
+
+    fn next(&mut self) -> Option<SubtreeKey> {
+        if self.leaf >= self.end_leaf {
+            return None;
+        }
+        let (height, index) = self.subtree(self.leaf);
+        self.leaf += 1 << height;
+        Some(SubtreeKey {
+            leaf_bits: self.root.leaf_bits,
+            height: height as u8,
+            index,
+            key: self.root.descendant_key(height as u8, index),
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let mut leaf = self.leaf;
+        let mut count = 0;
+        while leaf < self.end_leaf {
+            leaf += 1 << self.subtree(leaf).0;
+            count += 1;
+        }
+        (count, Some(count))
+    }
+}
+
+impl ExactSizeIterator for Delegation<'_> {}
+
+impl core::iter::FusedIterator for Delegation<'_> {}
+
+/// A cipher restricted to the ranges covered by a set of delegated subtree keys.
+#[derive(Clone, Copy)]
+pub struct RestrictedCipher<'a> {
+    /// The delegated keys
+    keys: &'a [SubtreeKey],
+}
+
+impl<'a> RestrictedCipher<'a> {
+    /// Create a cipher from keys returned by `KeyTree::delegate()`.
+    pub fn new(keys: &'a [SubtreeKey]) -> Result<Self, &'static str> {
+        let leaf_bits = keys.first().ok_or("no keys")?.leaf_bits;
+        for key in keys {
+            key.validate()?;
+        }
+        if keys.iter().any(|key| key.leaf_bits != leaf_bits) {
+            return Err("keys belong to trees with different leaf sizes");
+        }
+        Ok(RestrictedCipher { keys })
+    }
+
+    /// Fill the given buffer with the keystream starting at the given offset.
+    ///
+    /// The offset is in bytes.
+    /// The whole range must be covered by the delegated keys.
+    pub fn fill(&self, out: &mut [u8], start_offset: u64) -> Result<(), &'static str> {
+        process(self.keys, out, start_offset, false)
+    }
+
+    /// Encrypt or decrypt the given buffer in place, given the offset.
+    ///
+    /// The offset is in bytes.
+    /// The whole range must be covered by the delegated keys.
+    pub fn apply_keystream(&self, out: &mut [u8], start_offset: u64) -> Result<(), &'static str> {
+        process(self.keys, out, start_offset, true)
+    }
+}
+
+/// Fill or encrypt a buffer, leaf by leaf, using the subtree keys covering every leaf.
+fn process(
+    keys: &[SubtreeKey],
+    mut out: &mut [u8],
+    start_offset: u64,
+    xor: bool,
+) -> Result<(), &'static str> {
+    if start_offset.checked_add(out.len() as u64).is_none() {
+        return Err("offset would overflow");
+    }
+    let leaf_bits = keys[0].leaf_bits as u32;
+    if !out.is_empty() {
+        // Check that the whole range is covered before modifying the buffer
+        let last_leaf = (start_offset + out.len() as u64 - 1) >> leaf_bits;
+        let mut leaf = start_offset >> leaf_bits;
+        loop {
+            let key = covering_key(keys, leaf)?;
+            let next_leaf = (key.index as u128 + 1) << key.height;
+            if next_leaf > last_leaf as u128 {
+                break;
+            }
+            leaf = next_leaf as u64;
+        }
+    }
+    let mut offset = start_offset;
+    while !out.is_empty() {
+        let leaf = offset >> leaf_bits;
+        let offset_in_leaf = offset & ((1u64 << leaf_bits) - 1);
+        let len = cmp::min((1u64 << leaf_bits) - offset_in_leaf, out.len() as u64) as usize;
+        let key = covering_key(keys, leaf)?;
+        let mut leaf_key = key.leaf_key(leaf);
+        let st = StreamCipher::new(&leaf_key, b"leaf");
+        crate::zeroize(&mut leaf_key);
+        let (chunk, rest) = out.split_at_mut(len);
+        if xor {
+            st.apply_keystream(chunk, offset_in_leaf)?;
+        } else {
+            st.fill(chunk, offset_in_leaf)?;
+        }
+        out = rest;
+        offset += len as u64;
+    }
+    Ok(())
+}
+
+/// Find a subtree key covering the given leaf.
+fn covering_key(keys: &[SubtreeKey], leaf: u64) -> Result<&SubtreeKey, &'static str> {
+    keys.iter()
+        .find(|key| key.covers(leaf))
+        .ok_or("range is not covered by the delegated keys")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ggm() {
+        let mut key = [0u8; KeyTree::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let tree = KeyTree::new(&key, b"test", 8).unwrap();
+
+        let mut delegation = tree.delegate(1000, 5000).unwrap();
+        assert_eq!(delegation.len(), 4);
+        let mut keys: [SubtreeKey; 4] = core::array::from_fn(|_| delegation.next().unwrap());
+        assert!(delegation.next().is_none());
+        assert_eq!(keys[0].range(), (768, 1024));
+        assert_eq!(keys[3].range(), (4096, 5120));
+
+        let mut serialized = [0u8; SubtreeKey::LENGTH];
+        serialized.copy_from_slice(&keys[1].to_bytes());
+        keys[1] = SubtreeKey::from_bytes(&serialized).unwrap();
+
+        let restricted = RestrictedCipher::new(&keys).unwrap();
+
+        let mut out = [0u8; 4000];
+        tree.fill(&mut out, 1000).unwrap();
+        let mut out2 = [0u8; 4000];
+        restricted.fill(&mut out2, 1000).unwrap();
+        assert_eq!(out, out2);
+
+        let mut msg = [0u8; 100];
+        restricted.apply_keystream(&mut msg, 1000).unwrap();
+        assert_eq!(msg, out[..100]);
+
+        assert!(restricted.fill(&mut out2[..10], 760).is_err());
+        assert!(restricted.fill(&mut out2[..10], 5118).is_err());
+
+        let mut partial = [0x42u8; 200];
+        assert!(restricted.apply_keystream(&mut partial, 5000).is_err());
+        assert_eq!(partial, [0x42u8; 200]);
+        assert!(restricted.fill(&mut partial, 700).is_err());
+        assert_eq!(partial, [0x42u8; 200]);
+    }
+
+    #[test]
+    fn test_ggm_full_range() {
+        let mut key = [0u8; KeyTree::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let tree = KeyTree::new(&key, b"test", 30).unwrap();
+
+        let mut delegation = tree.delegate(0, u64::MAX).unwrap();
+        assert_eq!(delegation.len(), 1);
+        assert_eq!(delegation.next().unwrap().range(), (0, 1 << 64));
+    }
+
+    #[test]
+    fn test_ggm_invalid_keys() {
+        let key = |leaf_bits, height, index| SubtreeKey {
+            leaf_bits,
+            height,
+            index,
+            key: [0u8; StreamCipher::KEY_LENGTH],
+        };
+        assert!(RestrictedCipher::new(&[key(8, 0, 1)]).is_ok());
+        assert!(RestrictedCipher::new(&[key(0, 0, 0)]).is_err());
+        assert!(RestrictedCipher::new(&[key(8, 57, 0)]).is_err());
+        assert!(RestrictedCipher::new(&[key(8, 50, 64)]).is_err());
+        assert!(RestrictedCipher::new(&[key(8, 0, 1), key(8, 0, 1 << 56)]).is_err());
+        assert!(RestrictedCipher::new(&[key(8, 0, 1), key(9, 0, 1)]).is_err());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_ggm_provider() {
+        use crate::key::Key;
+        use crate::provider::MemoryKeyProvider;
+
+        let mut provider = MemoryKeyProvider::new();
+        provider.rotate(Key::from([1u8; Key::LENGTH])).unwrap();
+
+        let (key_id, tree) = KeyTree::new_with_active_key(&provider, b"test", 8).unwrap();
+        let mut c = [0u8; 1000];
+        tree.apply_keystream(&mut c, 0).unwrap();
+
+        provider.rotate(Key::from([2u8; Key::LENGTH])).unwrap();
+        let tree = KeyTree::new_with_key_id(&provider, key_id, b"test", 8).unwrap();
+        tree.apply_keystream(&mut c, 0).unwrap();
+        assert_eq!(c, [0u8; 1000]);
+        assert!(KeyTree::new_with_key_id(&provider, 2, b"test", 8).is_err());
+    }
+}
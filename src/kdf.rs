@@ -0,0 +1,210 @@
+use crate::any::Algorithm;
+use crate::provider::KeyProvider;
+use crate::{ascon, chacha, keccak};
+
+/// A hierarchical key derivation function.
+///
+/// A `Kdf` is a node of a tree of keys rooted at a master key. Every node can derive labeled
+/// children, and children of children, for example using a path such as `tenant/42/file/7`.
+///
+/// Child keys and output keys are derived using the Ascon-based stream cipher as a PRF,
+/// with distinct domains for the derivation of children and the derivation of output keys.
+/// Knowledge of a child key doesn't reveal anything about its parent or its siblings.
+///
+/// The master key should only be used with this KDF.
+#[derive(Clone)]
+pub struct Kdf {
+    /// The key of this node
+    key: [u8; ascon::StreamCipher::KEY_LENGTH],
+}
+
+impl Kdf {
+    /// The key length in bytes
+    pub const KEY_LENGTH: usize = ascon::StreamCipher::KEY_LENGTH;
+
+    /// The maximum length of a label in bytes
+    pub const MAX_LABEL_LENGTH: usize = 255;
+
+    /// The separator between labels in a path
+    pub const PATH_SEPARATOR: char = '/';
+
+    /// Domain for the derivation of output keys
+    const DOMAIN_OUTPUT: u8 = 0x00;
+
+    /// Domain for the derivation of children
+    const DOMAIN_CHILD: u8 = 0x01;
+
+    /// Domain for the derivation of algorithm-specific keys
+    const DOMAIN_ALGORITHM: u8 = 0x02;
+
+    /// Create the root of a key tree from a master key.
+    ///
+    /// The key must be 32 bytes long, and must be randomly generated.
+    pub fn new(master_key: &[u8; Self::KEY_LENGTH]) -> Self {
+        let mut key = [0u8; Self::KEY_LENGTH];
+        ascon::StreamCipher::new(master_key, b"seekable-stream-cipher/kdf")
+            .fill(&mut key, 0)
+            .unwrap();
+        Kdf { key }
+    }
+
+    /// Create the root of a key tree from the active key of a key provider.
+    ///
+    /// The ID of the key is returned along with the KDF. It must be stored with the derived
+    /// data, for `new_with_key_id()` to find the key again after the active key has been rotated.
+    pub fn new_with_active_key(provider: &impl KeyProvider) -> Result<(u64, Self), &'static str> {
+        let (key_id, key) = provider.active_key()?;
+        Ok((key_id, Self::new(&key)))
+    }
+
+    /// Create the root of a key tree from the key with the given ID.
+    pub fn new_with_key_id(provider: &impl KeyProvider, key_id: u64) -> Result<Self, &'static str> {
+        let key = provider.key(key_id)?;
+        Ok(Self::new(&key))
+    }
+
+    /// Derive a child with the given label.
+    ///
+    /// The label can be up to `MAX_LABEL_LENGTH` bytes long.
+    pub fn child(&self, label: impl AsRef<[u8]>) -> Result<Self, &'static str> {
+        let label = label.as_ref();
+        if label.len() > Self::MAX_LABEL_LENGTH {
+            return Err("label is too long");
+        }
+        let mut context = [0u8; 2 + Self::MAX_LABEL_LENGTH];
+        context[0] = Self::DOMAIN_CHILD;
+        context[1] = label.len() as u8;
+        context[2..][..label.len()].copy_from_slice(label);
+        let mut key = [0u8; Self::KEY_LENGTH];
+        ascon::StreamCipher::new(&self.key, &context[..2 + label.len()]).fill(&mut key, 0)?;
+        Ok(Kdf { key })
+    }
+
+    /// Derive a descendant from a path of labels separated by `PATH_SEPARATOR`.
+    ///
+    /// `kdf.derive_path("tenant/42")` is equivalent to `kdf.child("tenant")?.child("42")`.
+    /// Labels must not be empty.
+    pub fn derive_path(&self, path: &str) -> Result<Self, &'static str> {
+        let mut kdf = self.clone();
+        for label in path.split(Self::PATH_SEPARATOR) {
+            if label.is_empty() {
+                return Err("empty label in path");
+            }
+            kdf = kdf.child(label)?;
+        }
+        Ok(kdf)
+    }
+
+    /// Return the key associated with this node.
+    ///
+    /// The key should only be used with a single primitive.
+    /// `algorithm_key()` returns a distinct key for each stream cipher.
+    pub fn key(&self) -> [u8; Self::KEY_LENGTH] {
+        let mut key = [0u8; Self::KEY_LENGTH];
+        ascon::StreamCipher::new(&self.key, [Self::DOMAIN_OUTPUT])
+            .fill(&mut key, 0)
+            .unwrap();
+        key
+    }
+
+    /// Return the key associated with this node, for use with the given algorithm only.
+    ///
+    /// Different algorithms get independent keys, so that the same key is never used
+    /// with more than one primitive.
+    pub fn algorithm_key(&self, algorithm: Algorithm) -> [u8; Self::KEY_LENGTH] {
+        let mut key = [0u8; Self::KEY_LENGTH];
+        ascon::StreamCipher::new(&self.key, [Self::DOMAIN_ALGORITHM, algorithm.code()])
+            .fill(&mut key, 0)
+            .unwrap();
+        key
+    }
+
+    /// Return an Ascon-based stream cipher keyed with the key of this node.
+    pub fn ascon(&self, context: impl AsRef<[u8]>) -> ascon::StreamCipher {
+        let mut key = self.algorithm_key(Algorithm::Ascon);
+        let st = ascon::StreamCipher::new(&key, context);
+        crate::zeroize(&mut key);
+        st
+    }
+
+    /// Return a Keccak-based stream cipher keyed with the key of this node.
+    pub fn keccak(&self, context: impl AsRef<[u8]>) -> keccak::StreamCipher {
+        let mut key = self.algorithm_key(Algorithm::Keccak);
+        let st = keccak::StreamCipher::new(&key, context);
+        crate::zeroize(&mut key);
+        st
+    }
+
+    /// Return a ChaCha-based stream cipher keyed with the key of this node.
+    pub fn chacha(&self, id: &[u8; 8]) -> chacha::StreamCipher {
+        let mut key = self.algorithm_key(Algorithm::ChaCha);
+        let st = chacha::StreamCipher::new(&key, id);
+        crate::zeroize(&mut key);
+        st
+    }
+}
+
+impl Drop for Kdf {
+    fn drop(&mut self) {
+        crate::zeroize(&mut self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kdf() {
+        let mut master_key = [0u8; Kdf::KEY_LENGTH];
+        getrandom::fill(&mut master_key).unwrap();
+
+        let kdf = Kdf::new(&master_key);
+        let file = kdf.derive_path("tenant/42/file/7").unwrap();
+        let file2 = kdf
+            .child("tenant")
+            .unwrap()
+            .child("42")
+            .unwrap()
+            .child("file")
+            .unwrap()
+            .child("7")
+            .unwrap();
+        assert_eq!(file.key(), file2.key());
+        assert!(file.key() != kdf.derive_path("tenant/42/file/8").unwrap().key());
+        assert!(file.key() != kdf.derive_path("tenant/42/file").unwrap().key());
+        assert!(kdf.derive_path("tenant//42").is_err());
+
+        let mut out = [0u8; 100];
+        file.ascon(b"test").fill(&mut out, 0).unwrap();
+        let mut out2 = [0u8; 100];
+        ascon::StreamCipher::new(&file.algorithm_key(Algorithm::Ascon), b"test")
+            .fill(&mut out2, 0)
+            .unwrap();
+        assert_eq!(out, out2);
+
+        let keys = Algorithm::ALL.map(|algorithm| file.algorithm_key(algorithm));
+        assert!(keys[0] != keys[1] && keys[0] != keys[2] && keys[1] != keys[2]);
+        assert!(keys.iter().all(|key| *key != file.key()));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_kdf_provider() {
+        use crate::key::Key;
+        use crate::provider::MemoryKeyProvider;
+
+        let mut provider = MemoryKeyProvider::new();
+        provider.rotate(Key::from([1u8; Key::LENGTH])).unwrap();
+
+        let (key_id, kdf) = Kdf::new_with_active_key(&provider).unwrap();
+        let key = kdf.derive_path("tenant/42").unwrap().key();
+
+        provider.rotate(Key::from([2u8; Key::LENGTH])).unwrap();
+        let kdf = Kdf::new_with_key_id(&provider, key_id).unwrap();
+        assert_eq!(kdf.derive_path("tenant/42").unwrap().key(), key);
+        let (_, kdf) = Kdf::new_with_active_key(&provider).unwrap();
+        assert!(kdf.derive_path("tenant/42").unwrap().key() != key);
+        assert!(Kdf::new_with_key_id(&provider, 2).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+use core::fmt;
+use core::ops::Deref;
+
+#[cfg(any(feature = "hex", feature = "base64"))]
+use ct_codecs::{Decoder, Encoder};
+
+/// A secret key, accepted by all the stream ciphers.
+///
+/// A `&Key` can be used wherever a `&[u8; 32]` key is expected, for example
+/// `ascon::StreamCipher::new(&key, context)`.
+///
+/// The key is never printed by `Debug`, is compared in constant time, and is overwritten
+/// with zeros when dropped.
+#[derive(Clone)]
+pub struct Key([u8; Key::LENGTH]);
+
+impl Key {
+    /// The key length in bytes
+    pub const LENGTH: usize = 32;
+
+    /// The length of a hex-encoded key
+    #[cfg(feature = "hex")]
+    pub const HEX_LENGTH: usize = Self::LENGTH * 2;
+
+    /// The length of a base64-encoded key, including padding
+    #[cfg(feature = "base64")]
+    pub const BASE64_LENGTH: usize = Self::LENGTH.div_ceil(3) * 4;
+
+    /// Generate a new random key, using the operating system's random number generator.
+    #[cfg(feature = "getrandom")]
+    pub fn generate() -> Result<Self, &'static str> {
+        let mut key = [0u8; Self::LENGTH];
+        getrandom::fill(&mut key).map_err(|_| "unable to generate a random key")?;
+        Ok(Key(key))
+    }
+
+    /// Create a key from a slice, that must be exactly `LENGTH` bytes long.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, &'static str> {
+        let key = bytes.try_into().map_err(|_| "invalid key length")?;
+        Ok(Key(key))
+    }
+
+    /// Return the raw key.
+    pub fn as_bytes(&self) -> &[u8; Self::LENGTH] {
+        &self.0
+    }
+
+    /// Decode a hex-encoded key.
+    #[cfg(feature = "hex")]
+    pub fn from_hex(hex: impl AsRef<[u8]>) -> Result<Self, &'static str> {
+        let mut key = Key([0u8; Self::LENGTH]);
+        let len = ct_codecs::Hex::decode(&mut key.0, hex, None)
+            .map_err(|_| "invalid hex-encoded key")?
+            .len();
+        if len != Self::LENGTH {
+            return Err("invalid key length");
+        }
+        Ok(key)
+    }
+
+    /// Encode the key as hex into the given buffer, and return it as a string.
+    #[cfg(feature = "hex")]
+    pub fn to_hex<'a>(&self, out: &'a mut [u8; Self::HEX_LENGTH]) -> &'a str {
+        ct_codecs::Hex::encode_to_str(out, self.0).unwrap()
+    }
+
+    /// Decode a base64-encoded key, using the standard alphabet with padding.
+    #[cfg(feature = "base64")]
+    pub fn from_base64(b64: impl AsRef<[u8]>) -> Result<Self, &'static str> {
+        let mut key = Key([0u8; Self::LENGTH]);
+        let len = ct_codecs::Base64::decode(&mut key.0, b64, None)
+            .map_err(|_| "invalid base64-encoded key")?
+            .len();
+        if len != Self::LENGTH {
+            return Err("invalid key length");
+        }
+        Ok(key)
+    }
+
+    /// Encode the key as base64 into the given buffer, and return it as a string.
+    ///
+    /// The standard alphabet with padding is used.
+    #[cfg(feature = "base64")]
+    pub fn to_base64<'a>(&self, out: &'a mut [u8; Self::BASE64_LENGTH]) -> &'a str {
+        ct_codecs::Base64::encode_to_str(out, self.0).unwrap()
+    }
+}
+
+impl From<[u8; Key::LENGTH]> for Key {
+    fn from(key: [u8; Key::LENGTH]) -> Self {
+        Key(key)
+    }
+}
+
+impl Deref for Key {
+    type Target = [u8; Key::LENGTH];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Key {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl PartialEq for Key {
+    /// Compare keys in constant time.
+    fn eq(&self, other: &Self) -> bool {
+        crate::ct_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Key {}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Key([REDACTED])")
+    }
+}
+
+impl Drop for Key {
+    fn drop(&mut self) {
+        crate::zeroize(&mut self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascon::StreamCipher;
+
+    #[test]
+    fn test_key() {
+        let mut raw = [0u8; Key::LENGTH];
+        getrandom::fill(&mut raw).unwrap();
+
+        let key = Key::from(raw);
+        assert_eq!(key, Key::from_slice(&raw).unwrap());
+        assert!(key != Key::from([0u8; Key::LENGTH]));
+        assert!(Key::from_slice(&raw[1..]).is_err());
+
+        let mut out = [0u8; 100];
+        StreamCipher::new(&key, b"test").fill(&mut out, 0).unwrap();
+        let mut expected = [0u8; 100];
+        StreamCipher::new(&raw, b"test")
+            .fill(&mut expected, 0)
+            .unwrap();
+        assert_eq!(out, expected);
+    }
+
+    #[cfg(all(feature = "hex", feature = "base64", feature = "getrandom"))]
+    #[test]
+    fn test_key_encoding() {
+        let key = Key::generate().unwrap();
+
+        let mut hex = [0u8; Key::HEX_LENGTH];
+        assert_eq!(Key::from_hex(key.to_hex(&mut hex)).unwrap(), key);
+        assert!(Key::from_hex(&hex[..62]).is_err());
+        assert!(Key::from_hex("zz").is_err());
+
+        let mut b64 = [0u8; Key::BASE64_LENGTH];
+        assert_eq!(Key::from_base64(key.to_base64(&mut b64)).unwrap(), key);
+        assert!(Key::from_base64("AAAA").is_err());
+    }
+}
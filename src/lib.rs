@@ -1,7 +1,90 @@
 #![doc = include_str!("../README.md")]
 #![no_std]
-#![forbid(unsafe_code)]
+#![cfg_attr(not(feature = "uninit"), forbid(unsafe_code))]
+#![cfg_attr(feature = "uninit", deny(unsafe_code))]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+pub mod any;
 pub mod ascon;
 pub mod chacha;
+#[cfg(feature = "serde")]
+pub mod descriptor;
+pub mod ggm;
+pub mod kdf;
 pub mod keccak;
+pub mod key;
+#[cfg(feature = "argon2")]
+pub mod password;
+pub mod provider;
+pub mod ratchet;
+pub mod sector;
+pub mod seekable;
+pub mod shred;
+pub mod wideblock;
+pub mod wrap;
+
+/// Load a little-endian 64-bit word at the given position.
+pub(crate) const fn load_u64_le(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+        bytes[offset + 4],
+        bytes[offset + 5],
+        bytes[offset + 6],
+        bytes[offset + 7],
+    ])
+}
+
+/// Load a little-endian 32-bit word at the given position.
+pub(crate) const fn load_u32_le(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// Compare two secrets in constant time.
+pub(crate) fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut d = 0u8;
+    for (x, y) in a.iter().zip(b) {
+        d |= x ^ y;
+    }
+    core::hint::black_box(d) == 0
+}
+
+/// Derive a key from a master key, a domain and a context.
+///
+/// The domain key is only used to derive the output key, and is erased afterwards.
+pub(crate) fn derive_key(
+    key: &[u8; ascon::StreamCipher::KEY_LENGTH],
+    domain: &[u8],
+    context: &[u8],
+) -> [u8; ascon::StreamCipher::KEY_LENGTH] {
+    let mut domain_key = [0u8; ascon::StreamCipher::KEY_LENGTH];
+    ascon::StreamCipher::new(key, domain)
+        .fill(&mut domain_key, 0)
+        .unwrap();
+    let mut derived_key = [0u8; ascon::StreamCipher::KEY_LENGTH];
+    ascon::StreamCipher::new(&domain_key, context)
+        .fill(&mut derived_key, 0)
+        .unwrap();
+    zeroize(&mut domain_key);
+    derived_key
+}
+
+/// Overwrite a secret with zeros, in a way the compiler cannot easily elide.
+pub(crate) fn zeroize(secret: &mut [u8]) {
+    secret.fill(0);
+    core::hint::black_box(secret);
+}
@@ -0,0 +1,175 @@
+use crate::key::Key;
+
+/// Parameters for deriving a key from a password, using Argon2id.
+///
+/// The parameters are not secret. They must be stored next to the ciphertext, for example
+/// using `to_bytes()`, since the same parameters are required to derive the same key again.
+///
+/// # Caveats
+///
+/// * Parameters read from untrusted sources can request arbitrary amounts of memory and time.
+///   Applications should check `m_cost()` and `t_cost()` before calling `derive_key()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PasswordParams {
+    /// The memory cost, in KiB
+    m_cost: u32,
+    /// The number of passes
+    t_cost: u32,
+    /// The degree of parallelism
+    p_cost: u32,
+    /// The salt
+    salt: [u8; PasswordParams::SALT_LENGTH],
+}
+
+impl PasswordParams {
+    /// The salt length in bytes
+    pub const SALT_LENGTH: usize = 16;
+
+    /// The length of serialized parameters in bytes
+    pub const ENCODED_LENGTH: usize = 1 + 3 * 4 + Self::SALT_LENGTH;
+
+    /// The default memory cost, in KiB
+    pub const DEFAULT_M_COST: u32 = argon2::Params::DEFAULT_M_COST;
+
+    /// The default number of passes
+    pub const DEFAULT_T_COST: u32 = argon2::Params::DEFAULT_T_COST;
+
+    /// The default degree of parallelism
+    pub const DEFAULT_P_COST: u32 = argon2::Params::DEFAULT_P_COST;
+
+    /// The version of the serialized format, implying Argon2id v1.3
+    const VERSION: u8 = 1;
+
+    /// Create parameters with the given costs and salt.
+    ///
+    /// The salt must be unique per password, and should be randomly generated.
+    pub fn new(
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+        salt: [u8; Self::SALT_LENGTH],
+    ) -> Result<Self, &'static str> {
+        let params = PasswordParams {
+            m_cost,
+            t_cost,
+            p_cost,
+            salt,
+        };
+        params.argon2_params()?;
+        Ok(params)
+    }
+
+    /// Create parameters with the default costs and a random salt.
+    #[cfg(feature = "getrandom")]
+    pub fn generate() -> Result<Self, &'static str> {
+        let mut salt = [0u8; Self::SALT_LENGTH];
+        getrandom::fill(&mut salt).map_err(|_| "unable to generate a random salt")?;
+        Self::new(
+            Self::DEFAULT_M_COST,
+            Self::DEFAULT_T_COST,
+            Self::DEFAULT_P_COST,
+            salt,
+        )
+    }
+
+    /// Return the memory cost, in KiB.
+    pub fn m_cost(&self) -> u32 {
+        self.m_cost
+    }
+
+    /// Return the number of passes.
+    pub fn t_cost(&self) -> u32 {
+        self.t_cost
+    }
+
+    /// Return the degree of parallelism.
+    pub fn p_cost(&self) -> u32 {
+        self.p_cost
+    }
+
+    /// Return the salt.
+    pub fn salt(&self) -> &[u8; Self::SALT_LENGTH] {
+        &self.salt
+    }
+
+    /// Serialize the parameters.
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LENGTH] {
+        let mut out = [0u8; Self::ENCODED_LENGTH];
+        out[0] = Self::VERSION;
+        out[1..5].copy_from_slice(&self.m_cost.to_le_bytes());
+        out[5..9].copy_from_slice(&self.t_cost.to_le_bytes());
+        out[9..13].copy_from_slice(&self.p_cost.to_le_bytes());
+        out[13..].copy_from_slice(&self.salt);
+        out
+    }
+
+    /// Deserialize parameters previously serialized with `to_bytes()`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        if bytes.len() != Self::ENCODED_LENGTH {
+            return Err("invalid password parameters length");
+        }
+        if bytes[0] != Self::VERSION {
+            return Err("unsupported password parameters version");
+        }
+        Self::new(
+            crate::load_u32_le(bytes, 1),
+            crate::load_u32_le(bytes, 5),
+            crate::load_u32_le(bytes, 9),
+            bytes[13..].try_into().unwrap(),
+        )
+    }
+
+    /// Derive a key from a password.
+    ///
+    /// The key can be used with any of the stream ciphers.
+    pub fn derive_key(&self, password: impl AsRef<[u8]>) -> Result<Key, &'static str> {
+        let argon2 = argon2::Argon2::new(
+            argon2::Algorithm::Argon2id,
+            argon2::Version::V0x13,
+            self.argon2_params()?,
+        );
+        let mut key = [0u8; Key::LENGTH];
+        argon2
+            .hash_password_into(password.as_ref(), &self.salt, &mut key)
+            .map_err(|_| "password hashing failed")?;
+        let derived = Key::from(key);
+        crate::zeroize(&mut key);
+        Ok(derived)
+    }
+
+    fn argon2_params(&self) -> Result<argon2::Params, &'static str> {
+        argon2::Params::new(self.m_cost, self.t_cost, self.p_cost, Some(Key::LENGTH))
+            .map_err(|_| "invalid password parameters")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascon::StreamCipher;
+
+    #[test]
+    fn test_password() {
+        let mut salt = [0u8; PasswordParams::SALT_LENGTH];
+        getrandom::fill(&mut salt).unwrap();
+
+        let params = PasswordParams::new(64, 1, 1, salt).unwrap();
+        let key = params.derive_key("correct horse battery staple").unwrap();
+
+        let params2 = PasswordParams::from_bytes(&params.to_bytes()).unwrap();
+        assert_eq!(params, params2);
+        assert_eq!(
+            params2.derive_key("correct horse battery staple").unwrap(),
+            key
+        );
+        assert!(params2.derive_key("incorrect horse").unwrap() != key);
+
+        let mut out = [0u8; 100];
+        StreamCipher::new(&key, b"test").fill(&mut out, 0).unwrap();
+
+        assert!(PasswordParams::new(0, 1, 1, salt).is_err());
+        let mut bytes = params.to_bytes();
+        bytes[0] = 2;
+        assert!(PasswordParams::from_bytes(&bytes).is_err());
+    }
+}
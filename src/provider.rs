@@ -0,0 +1,261 @@
+use crate::key::Key;
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+/// A source of keys, identified by a key ID.
+///
+/// Multiple generations of keys can be kept, so that data encrypted with a previous key can
+/// still be decrypted after the active key has been rotated. Data should record the ID of
+/// the key it was encrypted with, and resolve it with `key()` when it is read back.
+pub trait KeyProvider {
+    /// Return the key with the given ID.
+    fn key(&self, key_id: u64) -> Result<Key, &'static str>;
+
+    /// Return the ID of the key that should be used to encrypt new data.
+    fn active_key_id(&self) -> Result<u64, &'static str>;
+
+    /// Return the ID and the key that should be used to encrypt new data.
+    fn active_key(&self) -> Result<(u64, Key), &'static str> {
+        let key_id = self.active_key_id()?;
+        Ok((key_id, self.key(key_id)?))
+    }
+}
+
+/// A key provider keeping all the keys in memory.
+#[cfg(feature = "alloc")]
+#[derive(Clone, Default)]
+pub struct MemoryKeyProvider {
+    /// The keys, along with their IDs
+    keys: Vec<(u64, Key)>,
+    /// The ID of the active key
+    active_key_id: Option<u64>,
+}
+
+#[cfg(feature = "alloc")]
+impl MemoryKeyProvider {
+    /// Create an empty key provider.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a key with the given ID.
+    ///
+    /// The first key added becomes the active key.
+    pub fn insert(&mut self, key_id: u64, key: Key) -> Result<(), &'static str> {
+        if self.keys.iter().any(|(id, _)| *id == key_id) {
+            return Err("key ID already exists");
+        }
+        self.keys.push((key_id, key));
+        if self.active_key_id.is_none() {
+            self.active_key_id = Some(key_id);
+        }
+        Ok(())
+    }
+
+    /// Make the key with the given ID the active key.
+    pub fn set_active(&mut self, key_id: u64) -> Result<(), &'static str> {
+        if !self.keys.iter().any(|(id, _)| *id == key_id) {
+            return Err("unknown key ID");
+        }
+        self.active_key_id = Some(key_id);
+        Ok(())
+    }
+
+    /// Add a new key with the next available ID, and make it the active key.
+    ///
+    /// The ID of the new key is returned. Previous keys are kept.
+    pub fn rotate(&mut self, key: Key) -> Result<u64, &'static str> {
+        let key_id = match self.keys.iter().map(|(id, _)| *id).max() {
+            None => 0,
+            Some(id) => id.checked_add(1).ok_or("no key ID available")?,
+        };
+        self.insert(key_id, key)?;
+        self.active_key_id = Some(key_id);
+        Ok(key_id)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl KeyProvider for MemoryKeyProvider {
+    fn key(&self, key_id: u64) -> Result<Key, &'static str> {
+        self.keys
+            .iter()
+            .find(|(id, _)| *id == key_id)
+            .map(|(_, key)| key.clone())
+            .ok_or("unknown key ID")
+    }
+
+    fn active_key_id(&self) -> Result<u64, &'static str> {
+        self.active_key_id.ok_or("no active key")
+    }
+}
+
+/// A key provider backed by a file.
+///
+/// The file is a sequence of records, each one made of a key ID encoded as a 64-bit
+/// little-endian integer, followed by the key. The last record is the active key.
+/// New keys are appended, so rotating keys never rewrites previous records.
+///
+/// The file contains raw keys, and must be protected accordingly. On unix, it is created
+/// with `0600` permissions, and an existing file that group or others can read is rejected.
+#[cfg(feature = "std")]
+pub struct FileKeyProvider {
+    /// The path to the file
+    path: std::path::PathBuf,
+    /// The keys loaded from the file
+    keys: MemoryKeyProvider,
+}
+
+#[cfg(feature = "std")]
+impl FileKeyProvider {
+    /// The size of a record in bytes
+    pub const RECORD_LENGTH: usize = 8 + Key::LENGTH;
+
+    /// Load the keys from the given file, creating it if it doesn't exist.
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, &'static str> {
+        use std::io::Read;
+
+        let path = path.as_ref().to_path_buf();
+        let mut file = Self::open_file(&path).map_err(|_| "unable to read the key file")?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let metadata = file.metadata().map_err(|_| "unable to read the key file")?;
+            if metadata.permissions().mode() & 0o044 != 0 {
+                return Err("key file must not be readable by group or others");
+            }
+        }
+        let mut data = Vec::new();
+        if file.read_to_end(&mut data).is_err() {
+            crate::zeroize(&mut data);
+            return Err("unable to read the key file");
+        }
+        if !data.len().is_multiple_of(Self::RECORD_LENGTH) {
+            crate::zeroize(&mut data);
+            return Err("invalid key file length");
+        }
+        let mut keys = MemoryKeyProvider::new();
+        let mut res = Ok(());
+        for record in data.chunks_exact(Self::RECORD_LENGTH) {
+            let key_id = crate::load_u64_le(record, 0);
+            res = keys
+                .insert(key_id, Key::from_slice(&record[8..]).unwrap())
+                .and_then(|_| keys.set_active(key_id));
+            if res.is_err() {
+                break;
+            }
+        }
+        crate::zeroize(&mut data);
+        res?;
+        Ok(FileKeyProvider { path, keys })
+    }
+
+    /// Add a new key with the next available ID, and make it the active key.
+    ///
+    /// The key is appended to the file before being used. Its ID is returned.
+    pub fn rotate(&mut self, key: Key) -> Result<u64, &'static str> {
+        use std::io::Write;
+
+        let mut keys = self.keys.clone();
+        let key_id = keys.rotate(key.clone())?;
+        let mut record = [0u8; Self::RECORD_LENGTH];
+        record[..8].copy_from_slice(&key_id.to_le_bytes());
+        record[8..].copy_from_slice(key.as_bytes());
+        let res = Self::open_file(&self.path).and_then(|mut file| {
+            file.write_all(&record)?;
+            file.sync_all()
+        });
+        crate::zeroize(&mut record);
+        res.map_err(|_| "unable to write the key file")?;
+        self.keys = keys;
+        Ok(key_id)
+    }
+
+    /// Open the key file for reading and appending, creating it if it doesn't exist.
+    fn open_file(path: &std::path::Path) -> std::io::Result<std::fs::File> {
+        let mut options = std::fs::OpenOptions::new();
+        options.read(true).append(true).create(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(path)
+    }
+}
+
+#[cfg(feature = "std")]
+impl KeyProvider for FileKeyProvider {
+    fn key(&self, key_id: u64) -> Result<Key, &'static str> {
+        self.keys.key(key_id)
+    }
+
+    fn active_key_id(&self) -> Result<u64, &'static str> {
+        self.keys.active_key_id()
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_provider() {
+        let mut provider = MemoryKeyProvider::new();
+        assert!(provider.active_key().is_err());
+
+        let key0 = Key::from([1u8; Key::LENGTH]);
+        let key1 = Key::from([2u8; Key::LENGTH]);
+        assert_eq!(provider.rotate(key0.clone()).unwrap(), 0);
+        assert_eq!(provider.rotate(key1.clone()).unwrap(), 1);
+        assert_eq!(provider.active_key().unwrap(), (1, key1));
+        assert_eq!(provider.key(0).unwrap(), key0);
+        assert!(provider.key(2).is_err());
+        assert!(provider.insert(0, key0).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_file_provider() {
+        struct RemoveOnDrop(std::path::PathBuf);
+
+        impl Drop for RemoveOnDrop {
+            fn drop(&mut self) {
+                let _ = std::fs::remove_file(&self.0);
+            }
+        }
+
+        let mut suffix = [0u8; 8];
+        getrandom::fill(&mut suffix).unwrap();
+        let mut path = std::env::temp_dir();
+        path.push(std::format!(
+            "seekable-stream-cipher-test-keys-{}-{:016x}",
+            std::process::id(),
+            u64::from_le_bytes(suffix)
+        ));
+        let path = RemoveOnDrop(path);
+        let path = &path.0;
+
+        let mut provider = FileKeyProvider::open(path).unwrap();
+        assert!(path.exists());
+        assert!(provider.active_key().is_err());
+
+        let key0 = Key::from([1u8; Key::LENGTH]);
+        let key1 = Key::from([2u8; Key::LENGTH]);
+        assert_eq!(provider.rotate(key0.clone()).unwrap(), 0);
+        assert_eq!(provider.rotate(key1.clone()).unwrap(), 1);
+
+        let provider = FileKeyProvider::open(path).unwrap();
+        assert_eq!(provider.active_key().unwrap(), (1, key1));
+        assert_eq!(provider.key(0).unwrap(), key0);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = std::fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(FileKeyProvider::open(path).is_err());
+        }
+    }
+}
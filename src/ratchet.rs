@@ -0,0 +1,263 @@
+use crate::ascon::StreamCipher;
+use core::cmp;
+
+/// A forward-secure key ratchet for long-lived, append-only streams.
+///
+/// The stream is split into epochs of `epoch_size` bytes. Every epoch is encrypted with its own
+/// key, and the key of an epoch is derived from the key of the previous epoch using a one-way
+/// function.
+///
+/// Only the key of the oldest retained epoch is stored. Keys of later epochs are derived from it
+/// on demand, so any retained epoch can be encrypted or decrypted using global offsets.
+/// Once the ratchet has been advanced, keys of previous epochs are erased and cannot be
+/// recomputed: a compromise of the current state doesn't expose data from erased epochs.
+///
+/// A writer should use `append()`, that advances the ratchet as soon as an epoch has been
+/// completed, so that it only ever retains the key of the epoch being written.
+/// `advance_to()` can be used to erase epochs explicitly, for example on a reader.
+///
+/// # Caveats
+///
+/// * There is no integrity.
+/// * Copies of a `Ratchet` keep their own keys, and must be advanced or dropped as well.
+/// * The initial key must not be retained elsewhere, e.g. by a `KeyProvider`, or erased epochs can
+///   be recomputed from it.
+#[derive(Clone)]
+pub struct Ratchet {
+    /// The chain key of the oldest retained epoch
+    chain_key: [u8; StreamCipher::KEY_LENGTH],
+    /// The oldest retained epoch
+    epoch: u64,
+    /// The epoch size in bytes
+    epoch_size: u64,
+    /// The global offset the next appended data will be written at
+    write_offset: u64,
+}
+
+impl Ratchet {
+    /// The key length in bytes
+    pub const KEY_LENGTH: usize = StreamCipher::KEY_LENGTH;
+
+    /// Create a new ratchet with the given key, context and epoch size, starting at epoch `0`.
+    ///
+    /// The key must be 32 bytes long, and must be randomly generated.
+    ///
+    /// The context is optional can be of any length. It is used to improve multi-user security.
+    pub fn new(
+        key: &[u8; Self::KEY_LENGTH],
+        context: impl AsRef<[u8]>,
+        epoch_size: u64,
+    ) -> Result<Self, &'static str> {
+        if epoch_size == 0 {
+            return Err("epoch size must not be zero");
+        }
+        let chain_key = crate::derive_key(key, b"seekable-stream-cipher/ratchet", context.as_ref());
+        Ok(Ratchet {
+            chain_key,
+            epoch: 0,
+            epoch_size,
+            write_offset: 0,
+        })
+    }
+
+    /// Return the oldest epoch whose key is still retained.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Return the epoch size in bytes.
+    pub fn epoch_size(&self) -> u64 {
+        self.epoch_size
+    }
+
+    /// Return the global offset the next appended data will be written at.
+    pub fn write_offset(&self) -> u64 {
+        self.write_offset
+    }
+
+    /// Map a global offset to an `(epoch, offset in epoch)` pair.
+    pub fn locate(&self, offset: u64) -> (u64, u64) {
+        (offset / self.epoch_size, offset % self.epoch_size)
+    }
+
+    /// Advance the ratchet to the given epoch, erasing the keys of all the previous epochs.
+    ///
+    /// The ratchet cannot move backwards.
+    pub fn advance_to(&mut self, epoch: u64) -> Result<(), &'static str> {
+        if epoch < self.epoch {
+            return Err("epoch key has been erased");
+        }
+        while self.epoch < epoch {
+            let (mut next_chain_key, mut data_key) = Self::step(&self.chain_key);
+            crate::zeroize(&mut data_key);
+            self.chain_key = next_chain_key;
+            crate::zeroize(&mut next_chain_key);
+            self.epoch += 1;
+        }
+        self.write_offset = self.write_offset.max(epoch.saturating_mul(self.epoch_size));
+        Ok(())
+    }
+
+    /// Encrypt the given buffer in place, and append it to the stream.
+    ///
+    /// The data is written at the current write offset, which is then moved past it.
+    /// If the write reaches the end of an epoch, the ratchet is advanced, and the keys of
+    /// all the completed epochs are erased.
+    ///
+    /// Returns the global offset the data was written at.
+    pub fn append(&mut self, buf: &mut [u8]) -> Result<u64, &'static str> {
+        let offset = self.write_offset;
+        let end = offset
+            .checked_add(buf.len() as u64)
+            .ok_or("offset would overflow")?;
+        self.apply_keystream(buf, offset)?;
+        self.write_offset = end;
+        self.advance_to(self.locate(end).0)?;
+        Ok(offset)
+    }
+
+    /// Fill the given buffer with the keystream starting at the given global offset.
+    ///
+    /// The offset is in bytes.
+    /// The keys of all the epochs covered by the range must still be retained.
+    pub fn fill(&self, out: &mut [u8], start_offset: u64) -> Result<(), &'static str> {
+        self.process(out, start_offset, false)
+    }
+
+    /// Encrypt or decrypt the given buffer in place, given the global offset.
+    ///
+    /// The offset is in bytes.
+    /// The keys of all the epochs covered by the range must still be retained.
+    pub fn apply_keystream(&self, out: &mut [u8], start_offset: u64) -> Result<(), &'static str> {
+        self.process(out, start_offset, true)
+    }
+
+    /// Fill or encrypt a buffer, epoch by epoch.
+    fn process(
+        &self,
+        mut out: &mut [u8],
+        start_offset: u64,
+        xor: bool,
+    ) -> Result<(), &'static str> {
+        if start_offset.checked_add(out.len() as u64).is_none() {
+            return Err("offset would overflow");
+        }
+        let (mut epoch, mut offset_in_epoch) = self.locate(start_offset);
+        if epoch < self.epoch {
+            return Err("epoch key has been erased");
+        }
+        let mut chain_key = self.chain_key;
+        for _ in self.epoch..epoch {
+            let (next_chain_key, mut data_key) = Self::step(&chain_key);
+            crate::zeroize(&mut data_key);
+            chain_key = next_chain_key;
+        }
+        while !out.is_empty() {
+            let len = cmp::min(self.epoch_size - offset_in_epoch, out.len() as u64) as usize;
+            let (next_chain_key, mut data_key) = Self::step(&chain_key);
+            let st = StreamCipher::new(&data_key, epoch.to_le_bytes());
+            crate::zeroize(&mut data_key);
+            let (chunk, rest) = out.split_at_mut(len);
+            if xor {
+                st.apply_keystream(chunk, offset_in_epoch)?;
+            } else {
+                st.fill(chunk, offset_in_epoch)?;
+            }
+            out = rest;
+            chain_key = next_chain_key;
+            epoch += 1;
+            offset_in_epoch = 0;
+        }
+        crate::zeroize(&mut chain_key);
+        Ok(())
+    }
+
+    /// Derive the next chain key and the data key from a chain key.
+    fn step(
+        chain_key: &[u8; StreamCipher::KEY_LENGTH],
+    ) -> (
+        [u8; StreamCipher::KEY_LENGTH],
+        [u8; StreamCipher::KEY_LENGTH],
+    ) {
+        let mut next_chain_key = [0u8; StreamCipher::KEY_LENGTH];
+        let mut data_key = [0u8; StreamCipher::KEY_LENGTH];
+        let st = StreamCipher::new(chain_key, b"ratchet");
+        st.fill(&mut next_chain_key, 0).unwrap();
+        st.fill(&mut data_key, StreamCipher::KEY_LENGTH as u64)
+            .unwrap();
+        (next_chain_key, data_key)
+    }
+}
+
+impl Drop for Ratchet {
+    fn drop(&mut self) {
+        crate::zeroize(&mut self.chain_key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ratchet() {
+        let mut key = [0u8; Ratchet::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let mut writer = Ratchet::new(&key, b"telemetry", 100).unwrap();
+        let reader = writer.clone();
+
+        let msg = [0u8; 350];
+        let mut c = msg;
+        writer.apply_keystream(&mut c, 0).unwrap();
+
+        writer.advance_to(2).unwrap();
+        assert_eq!(writer.epoch(), 2);
+        assert_eq!(writer.locate(250), (2, 50));
+
+        let mut p = [0u8; 120];
+        p.copy_from_slice(&c[230..350]);
+        writer.apply_keystream(&mut p, 230).unwrap();
+        assert_eq!(p, msg[230..350]);
+
+        assert!(writer.apply_keystream(&mut p, 150).is_err());
+        assert!(writer.advance_to(1).is_err());
+
+        let mut p = [0u8; 350];
+        p.copy_from_slice(&c);
+        reader.apply_keystream(&mut p, 0).unwrap();
+        assert_eq!(p, msg);
+    }
+
+    #[test]
+    fn test_ratchet_append() {
+        let mut key = [0u8; Ratchet::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let mut writer = Ratchet::new(&key, b"telemetry", 100).unwrap();
+        let reader = writer.clone();
+
+        let msg = [0u8; 350];
+        let mut c = msg;
+        assert_eq!(writer.append(&mut c[..60]).unwrap(), 0);
+        assert_eq!(writer.epoch(), 0);
+        assert_eq!(writer.append(&mut c[60..100]).unwrap(), 60);
+        assert_eq!(writer.epoch(), 1);
+        assert_eq!(writer.append(&mut c[100..350]).unwrap(), 100);
+        assert_eq!(writer.epoch(), 3);
+        assert_eq!(writer.write_offset(), 350);
+        assert!(writer.fill(&mut [0u8; 10], 290).is_err());
+
+        let mut expected = msg;
+        reader.apply_keystream(&mut expected, 0).unwrap();
+        assert_eq!(c, expected);
+
+        writer.advance_to(5).unwrap();
+        assert_eq!(writer.write_offset(), 500);
+        let mut c = [0u8; 10];
+        assert_eq!(writer.append(&mut c).unwrap(), 500);
+        let mut expected = [0u8; 10];
+        reader.apply_keystream(&mut expected, 500).unwrap();
+        assert_eq!(c, expected);
+    }
+}
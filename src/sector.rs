@@ -0,0 +1,296 @@
+use crate::ascon::StreamCipher;
+use crate::provider::KeyProvider;
+
+/// A table of per-sector write counters.
+///
+/// The table is a plain byte slice owned by the application. It starts with a header recording
+/// the ID of the key the volume is encrypted with, if any, followed by a 4-byte little-endian
+/// counter per sector. It must be persisted along with the encrypted sectors, as it is
+/// required to decrypt them.
+///
+/// A counter set to `0` means that the sector has never been written.
+pub struct VersionTable<'a> {
+    /// The serialized header and counters
+    versions: &'a mut [u8],
+}
+
+impl<'a> VersionTable<'a> {
+    /// The size of the table header in bytes
+    pub const HEADER_LENGTH: usize = 1 + 8;
+
+    /// The size of a table entry in bytes
+    pub const ENTRY_LENGTH: usize = 4;
+
+    /// Use the given buffer as a version table.
+    ///
+    /// The buffer must be `HEADER_LENGTH` bytes, plus `ENTRY_LENGTH` bytes per sector.
+    /// A new table must be filled with zeros, and an existing table can be loaded by passing
+    /// its previously persisted content.
+    pub fn new(versions: &'a mut [u8]) -> Result<Self, &'static str> {
+        if versions.len() < Self::HEADER_LENGTH
+            || !(versions.len() - Self::HEADER_LENGTH).is_multiple_of(Self::ENTRY_LENGTH)
+        {
+            return Err("invalid version table length");
+        }
+        if versions[0] > 1 {
+            return Err("invalid version table header");
+        }
+        Ok(VersionTable { versions })
+    }
+
+    /// Return the number of sectors tracked by the table.
+    pub fn sectors(&self) -> u64 {
+        ((self.versions.len() - Self::HEADER_LENGTH) / Self::ENTRY_LENGTH) as u64
+    }
+
+    /// Return the ID of the key the volume is encrypted with, if it has been recorded.
+    pub fn key_id(&self) -> Option<u64> {
+        (self.versions[0] == 1).then(|| crate::load_u64_le(self.versions, 1))
+    }
+
+    /// Record the ID of the key the volume is encrypted with.
+    fn set_key_id(&mut self, key_id: u64) {
+        self.versions[0] = 1;
+        self.versions[1..Self::HEADER_LENGTH].copy_from_slice(&key_id.to_le_bytes());
+    }
+
+    /// Return the current version of the given sector.
+    pub fn version(&self, sector: u64) -> Result<u32, &'static str> {
+        let entry = self.entry(sector)?;
+        Ok(u32::from_le_bytes(
+            self.versions[entry..][..Self::ENTRY_LENGTH]
+                .try_into()
+                .unwrap(),
+        ))
+    }
+
+    /// Return the serialized table, to be persisted by the application.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.versions
+    }
+
+    /// Increment the version of the given sector, and return the new version.
+    fn bump(&mut self, sector: u64) -> Result<u32, &'static str> {
+        let entry = self.entry(sector)?;
+        let version = self
+            .version(sector)?
+            .checked_add(1)
+            .ok_or("sector version would overflow")?;
+        self.versions[entry..][..Self::ENTRY_LENGTH].copy_from_slice(&version.to_le_bytes());
+        Ok(version)
+    }
+
+    fn entry(&self, sector: u64) -> Result<usize, &'static str> {
+        if sector >= self.sectors() {
+            return Err("sector out of range");
+        }
+        Ok(Self::HEADER_LENGTH + sector as usize * Self::ENTRY_LENGTH)
+    }
+}
+
+/// Sector-oriented encryption that never reuses a keystream when a sector is overwritten.
+///
+/// Every write increments the sector version stored in a `VersionTable`, and the keystream
+/// is derived from the `(sector, version)` pair. Reads remain random-access within a sector.
+///
+/// # Caveats
+///
+/// * There is no integrity.
+/// * The version table is not authenticated. An adversary able to roll it back can force keystream reuse.
+#[derive(Clone)]
+pub struct SectorCipher {
+    /// The key derived from the master key and the volume context
+    volume_key: [u8; StreamCipher::KEY_LENGTH],
+    /// The sector size in bytes
+    sector_size: usize,
+}
+
+impl SectorCipher {
+    /// The key length in bytes
+    pub const KEY_LENGTH: usize = StreamCipher::KEY_LENGTH;
+
+    /// Create a new sector cipher with the given key, context and sector size.
+    ///
+    /// The key must be 32 bytes long, and must be randomly generated.
+    ///
+    /// The context is optional can be of any length. It should identify the volume.
+    pub fn new(
+        key: &[u8; Self::KEY_LENGTH],
+        context: impl AsRef<[u8]>,
+        sector_size: usize,
+    ) -> Result<Self, &'static str> {
+        if sector_size == 0 {
+            return Err("sector size must not be zero");
+        }
+        let volume_key = crate::derive_key(key, b"seekable-stream-cipher/sector", context.as_ref());
+        Ok(SectorCipher {
+            volume_key,
+            sector_size,
+        })
+    }
+
+    /// Create a new sector cipher using the active key of a key provider, for a new volume.
+    ///
+    /// The ID of the key is recorded in the version table, so that `new_with_key_id()` can find
+    /// the key again after the active key has been rotated. The table must not already record a key ID.
+    pub fn new_with_active_key(
+        provider: &impl KeyProvider,
+        versions: &mut VersionTable,
+        context: impl AsRef<[u8]>,
+        sector_size: usize,
+    ) -> Result<Self, &'static str> {
+        if versions.key_id().is_some() {
+            return Err("version table already records a key ID");
+        }
+        let (key_id, key) = provider.active_key()?;
+        let sc = Self::new(&key, context, sector_size)?;
+        versions.set_key_id(key_id);
+        Ok(sc)
+    }
+
+    /// Create a sector cipher for an existing volume, using the key whose ID is recorded in the version table.
+    pub fn new_with_key_id(
+        provider: &impl KeyProvider,
+        versions: &VersionTable,
+        context: impl AsRef<[u8]>,
+        sector_size: usize,
+    ) -> Result<Self, &'static str> {
+        let key_id = versions.key_id().ok_or("version table has no key ID")?;
+        let key = provider.key(key_id)?;
+        Self::new(&key, context, sector_size)
+    }
+
+    /// Return the sector size in bytes.
+    pub fn sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    /// Encrypt a full sector in place, and record the new sector version in the table.
+    ///
+    /// The buffer must be exactly one sector long.
+    /// Returns the new version of the sector.
+    pub fn write_sector(
+        &self,
+        versions: &mut VersionTable,
+        sector: u64,
+        buf: &mut [u8],
+    ) -> Result<u32, &'static str> {
+        if buf.len() != self.sector_size {
+            return Err("buffer must be exactly one sector long");
+        }
+        let version = versions.bump(sector)?;
+        self.apply_keystream(sector, version, buf, 0)?;
+        Ok(version)
+    }
+
+    /// Decrypt a range of a sector in place, using the version recorded in the table.
+    ///
+    /// The offset is in bytes, relative to the beginning of the sector.
+    pub fn read_sector(
+        &self,
+        versions: &VersionTable,
+        sector: u64,
+        buf: &mut [u8],
+        offset_in_sector: usize,
+    ) -> Result<(), &'static str> {
+        let version = versions.version(sector)?;
+        if version == 0 {
+            return Err("sector has never been written");
+        }
+        self.apply_keystream(sector, version, buf, offset_in_sector)
+    }
+
+    /// Encrypt or decrypt a range of a sector in place, given an explicit version.
+    ///
+    /// The offset is in bytes, relative to the beginning of the sector.
+    /// The same `(sector, version)` pair must never be used to encrypt different data.
+    pub fn apply_keystream(
+        &self,
+        sector: u64,
+        version: u32,
+        buf: &mut [u8],
+        offset_in_sector: usize,
+    ) -> Result<(), &'static str> {
+        if offset_in_sector
+            .checked_add(buf.len())
+            .is_none_or(|end| end > self.sector_size)
+        {
+            return Err("range exceeds the sector size");
+        }
+        let mut context = [0u8; 12];
+        context[0..8].copy_from_slice(&sector.to_le_bytes());
+        context[8..12].copy_from_slice(&version.to_le_bytes());
+        StreamCipher::new(&self.volume_key, context).apply_keystream(buf, offset_in_sector as u64)
+    }
+}
+
+impl Drop for SectorCipher {
+    fn drop(&mut self) {
+        crate::zeroize(&mut self.volume_key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sector() {
+        let mut key = [0u8; SectorCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let sc = SectorCipher::new(&key, b"test volume", 4096).unwrap();
+        let mut table = [0u8; VersionTable::HEADER_LENGTH + 8 * VersionTable::ENTRY_LENGTH];
+        let mut versions = VersionTable::new(&mut table).unwrap();
+
+        let msg = [42u8; 4096];
+        let mut c1 = msg;
+        assert_eq!(sc.write_sector(&mut versions, 3, &mut c1).unwrap(), 1);
+        let mut c2 = msg;
+        assert_eq!(sc.write_sector(&mut versions, 3, &mut c2).unwrap(), 2);
+        assert!(c1 != c2);
+
+        let mut partial = [0u8; 100];
+        partial.copy_from_slice(&c2[100..200]);
+        sc.read_sector(&versions, 3, &mut partial, 100).unwrap();
+        assert_eq!(partial, msg[100..200]);
+
+        let mut unwritten = [0u8; 16];
+        assert!(sc.read_sector(&versions, 4, &mut unwritten, 0).is_err());
+        assert!(sc.read_sector(&versions, 8, &mut unwritten, 0).is_err());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_sector_provider() {
+        use crate::key::Key;
+        use crate::provider::MemoryKeyProvider;
+
+        let mut provider = MemoryKeyProvider::new();
+        provider.rotate(Key::from([1u8; Key::LENGTH])).unwrap();
+
+        let mut table = [0u8; VersionTable::HEADER_LENGTH + VersionTable::ENTRY_LENGTH];
+        let mut versions = VersionTable::new(&mut table).unwrap();
+        assert_eq!(versions.key_id(), None);
+        let sc = SectorCipher::new_with_active_key(&provider, &mut versions, b"test volume", 512)
+            .unwrap();
+        assert_eq!(versions.key_id(), Some(0));
+        let mut c = [0u8; 512];
+        sc.write_sector(&mut versions, 0, &mut c).unwrap();
+        assert!(
+            SectorCipher::new_with_active_key(&provider, &mut versions, b"test volume", 512)
+                .is_err()
+        );
+
+        provider.rotate(Key::from([2u8; Key::LENGTH])).unwrap();
+        let mut persisted = table;
+        let versions = VersionTable::new(&mut persisted).unwrap();
+        let sc = SectorCipher::new_with_key_id(&provider, &versions, b"test volume", 512).unwrap();
+        sc.read_sector(&versions, 0, &mut c, 0).unwrap();
+        assert_eq!(c, [0u8; 512]);
+
+        let mut empty = [0u8; VersionTable::HEADER_LENGTH];
+        let versions = VersionTable::new(&mut empty).unwrap();
+        assert!(SectorCipher::new_with_key_id(&provider, &versions, b"test volume", 512).is_err());
+    }
+}
@@ -0,0 +1,888 @@
+use core::cmp;
+use core::iter::FusedIterator;
+use core::ops::DerefMut;
+
+/// A keyed function computing key stream blocks from their index.
+///
+/// This is what the stream ciphers of this crate are built on. Any keyed permutation or PRF
+/// can implement it, and get a seekable stream cipher using `Seekable`.
+///
+/// Block `i` is the key stream at offset `i * BLOCK_SIZE`.
+///
+/// `Seekable::new()` checks at compile time that `BLOCK_SIZE` is not zero, and that `Block` is
+/// `BLOCK_SIZE` bytes long.
+pub trait KeystreamBlock {
+    /// The block size in bytes
+    const BLOCK_SIZE: usize;
+
+    /// A key stream block, which must be exactly `BLOCK_SIZE` bytes long
+    type Block: AsRef<[u8]> + Copy;
+
+    /// Return the key stream block at the given block index.
+    fn block(&self, index: u64) -> Self::Block;
+
+    /// Store the key stream block at the given block index.
+    ///
+    /// The buffer is exactly `BLOCK_SIZE` bytes long.
+    /// Implementations can override this to avoid an intermediate copy.
+    fn store_block(&self, out: &mut [u8], index: u64) {
+        out.copy_from_slice(self.block(index).as_ref());
+    }
+
+    /// Add the key stream block at the given block index to the buffer.
+    ///
+    /// The buffer is exactly `BLOCK_SIZE` bytes long.
+    /// Implementations can override this to avoid an intermediate copy.
+    fn xor_block(&self, out: &mut [u8], index: u64) {
+        for (x, k) in out.iter_mut().zip(self.block(index).as_ref()) {
+            *x ^= k;
+        }
+    }
+
+    /// Add the key stream block at the given block index to `input`, and store the result in `output`.
+    ///
+    /// Both buffers are exactly `BLOCK_SIZE` bytes long.
+    /// Implementations can override this to avoid an intermediate copy.
+    fn xor_block_inout(&self, input: &[u8], output: &mut [u8], index: u64) {
+        for ((y, x), k) in output.iter_mut().zip(input).zip(self.block(index).as_ref()) {
+            *y = x ^ k;
+        }
+    }
+}
+
+/// A seekable stream cipher built on a `KeystreamBlock` implementation.
+///
+/// This takes care of splitting buffers into blocks, and of all the offset arithmetic.
+#[derive(Clone, Copy)]
+pub struct Seekable<B> {
+    /// The block function
+    inner: B,
+}
+
+impl<B: KeystreamBlock> Seekable<B> {
+    /// Create a seekable stream cipher from a block function.
+    ///
+    /// Compilation fails if the block size of the block function is inconsistent:
+    ///
+    /// ```compile_fail
+    /// use seekable_stream_cipher::seekable::{KeystreamBlock, Seekable};
+    ///
+    /// struct Empty;
+    ///
+    /// impl KeystreamBlock for Empty {
+    ///     const BLOCK_SIZE: usize = 0;
+    ///     type Block = [u8; 0];
+    ///
+    ///     fn block(&self, _index: u64) -> Self::Block {
+    ///         []
+    ///     }
+    /// }
+    ///
+    /// let _ = Seekable::new(Empty);
+    /// ```
+    pub fn new(inner: B) -> Self {
+        const { check_block_size::<B>() };
+        Seekable { inner }
+    }
+
+    /// Return the block function.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Return the block function, consuming the stream cipher.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    /// Fill the given buffer with the keystream starting at the given offset.
+    ///
+    /// The offset is in bytes.
+    pub fn fill(&self, out: &mut [u8], start_offset: u64) -> Result<(), &'static str> {
+        fill(&self.inner, out, start_offset)
+    }
+
+    /// Encrypt or decrypt the given buffer in place, given the offset.
+    ///
+    /// The offset is in bytes.
+    /// This function is equivalent to calling `fill` and then XORing the output with the input.
+    pub fn apply_keystream(&self, out: &mut [u8], start_offset: u64) -> Result<(), &'static str> {
+        apply_keystream(&self.inner, out, start_offset)
+    }
+
+    /// Encrypt or decrypt a list of buffers in place, as if they were a single contiguous buffer.
+    ///
+    /// The offset is in bytes, and is the offset of the first buffer.
+    pub fn apply_keystream_vectored<T: DerefMut<Target = [u8]>>(
+        &self,
+        bufs: &mut [T],
+        start_offset: u64,
+    ) -> Result<(), &'static str> {
+        apply_keystream_vectored(&self.inner, bufs, start_offset)
+    }
+
+    /// Encrypt or decrypt disjoint ranges in place, each one given as an `(offset, buffer)` pair.
+    ///
+    /// Offsets are in bytes.
+    pub fn apply_keystream_sparse(
+        &self,
+        segments: &mut [(u64, &mut [u8])],
+    ) -> Result<(), &'static str> {
+        apply_keystream_sparse(&self.inner, segments)
+    }
+
+    /// Fill uninitialized memory with the keystream starting at the given offset.
+    ///
+    /// The offset is in bytes. The initialized buffer is returned.
+    #[cfg(feature = "uninit")]
+    pub fn fill_uninit<'a>(
+        &self,
+        out: &'a mut [core::mem::MaybeUninit<u8>],
+        start_offset: u64,
+    ) -> Result<&'a mut [u8], &'static str> {
+        fill_uninit(&self.inner, out, start_offset)
+    }
+
+    /// Encrypt or decrypt `input` into `output`, given the offset.
+    ///
+    /// The offset is in bytes.
+    /// Both buffers must have the same length.
+    pub fn apply_keystream_inout(
+        &self,
+        input: &[u8],
+        output: &mut [u8],
+        start_offset: u64,
+    ) -> Result<(), &'static str> {
+        apply_keystream_inout(&self.inner, input, output, start_offset)
+    }
+
+    /// Encrypt or decrypt an `InOutBuf`, given the offset.
+    ///
+    /// The offset is in bytes.
+    #[cfg(feature = "inout")]
+    pub fn apply_keystream_inout_buf(
+        &self,
+        buf: inout::InOutBuf<'_, '_, u8>,
+        start_offset: u64,
+    ) -> Result<(), &'static str> {
+        apply_keystream_inout_buf(&self.inner, buf, start_offset)
+    }
+
+    /// Fill the given buffer with the keystream starting at the given 128-bit offset.
+    ///
+    /// The offset is in bytes, and can address the entire key stream, which is `2^64` blocks long.
+    pub fn fill_wide(&self, out: &mut [u8], start_offset: u128) -> Result<(), &'static str> {
+        fill_wide(&self.inner, out, start_offset)
+    }
+
+    /// Encrypt or decrypt the given buffer in place, given a 128-bit offset.
+    ///
+    /// The offset is in bytes, and can address the entire key stream, which is `2^64` blocks long.
+    pub fn apply_keystream_wide(
+        &self,
+        out: &mut [u8],
+        start_offset: u128,
+    ) -> Result<(), &'static str> {
+        apply_keystream_wide(&self.inner, out, start_offset)
+    }
+
+    /// Fill the given buffer with the keystream starting at the beginning of the given block.
+    ///
+    /// The buffer length doesn't have to be a multiple of the block size.
+    pub fn fill_blocks(&self, out: &mut [u8], first_block: u64) -> Result<(), &'static str> {
+        fill_blocks(&self.inner, out, first_block)
+    }
+
+    /// Encrypt or decrypt the given buffer in place, starting at the beginning of the given block.
+    ///
+    /// The buffer length doesn't have to be a multiple of the block size.
+    pub fn apply_blocks(&self, out: &mut [u8], first_block: u64) -> Result<(), &'static str> {
+        apply_blocks(&self.inner, out, first_block)
+    }
+}
+
+impl<B: KeystreamBlock + Clone> Seekable<B> {
+    /// Return an iterator over the bytes of the key stream, starting at the given offset.
+    ///
+    /// The iterator ends at offset `u64::MAX`.
+    pub fn keystream_bytes(&self, start_offset: u64) -> KeystreamBytes<B> {
+        KeystreamBytes::new(self.inner.clone(), start_offset)
+    }
+
+    /// Return an iterator over `len` bytes of the key stream, starting at the given offset.
+    ///
+    /// The range is truncated at offset `u64::MAX`.
+    pub fn keystream_bytes_range(
+        &self,
+        start_offset: u64,
+        len: usize,
+    ) -> Bounded<KeystreamBytes<B>> {
+        KeystreamBytes::new_range(self.inner.clone(), start_offset, len)
+    }
+
+    /// Return an iterator over the key stream blocks, starting at the given block index.
+    ///
+    /// The iterator ends after block `u64::MAX`.
+    pub fn keystream_blocks(&self, first_block: u64) -> KeystreamBlocks<B> {
+        KeystreamBlocks::new(self.inner.clone(), first_block)
+    }
+
+    /// Return an iterator over `count` key stream blocks, starting at the given block index.
+    ///
+    /// The range is truncated after block `u64::MAX`.
+    pub fn keystream_blocks_range(
+        &self,
+        first_block: u64,
+        count: usize,
+    ) -> Bounded<KeystreamBlocks<B>> {
+        KeystreamBlocks::new_range(self.inner.clone(), first_block, count)
+    }
+}
+
+/// Check that the block size of a `KeystreamBlock` implementation is consistent.
+const fn check_block_size<B: KeystreamBlock>() {
+    assert!(B::BLOCK_SIZE > 0, "BLOCK_SIZE must not be zero");
+    assert!(
+        core::mem::size_of::<B::Block>() == B::BLOCK_SIZE,
+        "Block must be BLOCK_SIZE bytes long"
+    );
+}
+
+pub(crate) fn fill<B: KeystreamBlock>(
+    b: &B,
+    out: &mut [u8],
+    start_offset: u64,
+) -> Result<(), &'static str> {
+    if start_offset.checked_add(out.len() as u64).is_none() {
+        return Err("offset would overflow");
+    }
+    let block_offset = start_offset / B::BLOCK_SIZE as u64;
+    let offset_in_first_block = (start_offset % B::BLOCK_SIZE as u64) as usize;
+    fill_from_block(b, out, block_offset, offset_in_first_block);
+    Ok(())
+}
+
+pub(crate) fn apply_keystream<B: KeystreamBlock>(
+    b: &B,
+    out: &mut [u8],
+    start_offset: u64,
+) -> Result<(), &'static str> {
+    if start_offset.checked_add(out.len() as u64).is_none() {
+        return Err("offset would overflow");
+    }
+    let block_offset = start_offset / B::BLOCK_SIZE as u64;
+    let offset_in_first_block = (start_offset % B::BLOCK_SIZE as u64) as usize;
+    apply_from_block(b, out, block_offset, offset_in_first_block);
+    Ok(())
+}
+
+pub(crate) fn apply_keystream_vectored<B: KeystreamBlock, T: DerefMut<Target = [u8]>>(
+    b: &B,
+    bufs: &mut [T],
+    start_offset: u64,
+) -> Result<(), &'static str> {
+    let mut end_offset = start_offset;
+    for buf in bufs.iter() {
+        end_offset = end_offset
+            .checked_add(buf.len() as u64)
+            .ok_or("offset would overflow")?;
+    }
+    let mut cache = None;
+    let mut offset = start_offset;
+    for buf in bufs.iter_mut() {
+        apply_segment(b, buf, offset, &mut cache);
+        offset += buf.len() as u64;
+    }
+    Ok(())
+}
+
+pub(crate) fn apply_keystream_sparse<B: KeystreamBlock>(
+    b: &B,
+    segments: &mut [(u64, &mut [u8])],
+) -> Result<(), &'static str> {
+    for (offset, buf) in segments.iter() {
+        if offset.checked_add(buf.len() as u64).is_none() {
+            return Err("offset would overflow");
+        }
+    }
+    let mut cache = None;
+    for (offset, buf) in segments.iter_mut() {
+        apply_segment(b, buf, *offset, &mut cache);
+    }
+    Ok(())
+}
+
+/// Add the keystream to a segment starting at the given offset.
+///
+/// Full blocks are processed by `apply_from_block`. The last partial block is kept in `cache`,
+/// so that it can be reused by the next segment.
+fn apply_segment<B: KeystreamBlock>(
+    b: &B,
+    mut out: &mut [u8],
+    offset: u64,
+    cache: &mut Option<(u64, B::Block)>,
+) {
+    let mut block_offset = offset / B::BLOCK_SIZE as u64;
+    let mut offset_in_block = (offset % B::BLOCK_SIZE as u64) as usize;
+    while !out.is_empty() {
+        if offset_in_block == 0 && out.len() >= B::BLOCK_SIZE {
+            let len = out.len() - out.len() % B::BLOCK_SIZE;
+            let (blocks, rest) = out.split_at_mut(len);
+            apply_from_block(b, blocks, block_offset, 0);
+            out = rest;
+            block_offset += (len / B::BLOCK_SIZE) as u64;
+            continue;
+        }
+        let rate = match cache {
+            Some((cached_offset, rate)) if *cached_offset == block_offset => *rate,
+            _ => {
+                let rate = b.block(block_offset);
+                *cache = Some((block_offset, rate));
+                rate
+            }
+        };
+        let len = cmp::min(B::BLOCK_SIZE - offset_in_block, out.len());
+        let (chunk, rest) = out.split_at_mut(len);
+        for (x, k) in chunk.iter_mut().zip(&rate.as_ref()[offset_in_block..]) {
+            *x ^= k;
+        }
+        out = rest;
+        block_offset += 1;
+        offset_in_block = 0;
+    }
+}
+
+/// Fill a buffer with words of the keystream, starting at the given word offset.
+///
+/// `words` returns the key stream block at the given index, as `N` words.
+pub(crate) fn fill_words<B: KeystreamBlock, T: Copy, const N: usize>(
+    b: &B,
+    mut out: &mut [T],
+    word_offset: u64,
+    words: impl Fn(&B, u64) -> [T; N],
+) -> Result<(), &'static str> {
+    const { assert!(N * core::mem::size_of::<T>() == B::BLOCK_SIZE) };
+    if word_offset
+        .checked_add(out.len() as u64)
+        .and_then(|end| end.checked_mul(core::mem::size_of::<T>() as u64))
+        .is_none()
+    {
+        return Err("offset would overflow");
+    }
+    let mut block_offset = word_offset / N as u64;
+    let mut offset_in_block = (word_offset % N as u64) as usize;
+    while !out.is_empty() {
+        let block = words(b, block_offset);
+        let len = cmp::min(N - offset_in_block, out.len());
+        out[..len].copy_from_slice(&block[offset_in_block..][..len]);
+        out = &mut out[len..];
+        block_offset += 1;
+        offset_in_block = 0;
+    }
+    Ok(())
+}
+
+/// Write the keystream into uninitialized memory, starting at the given offset.
+///
+/// This is the only function of the crate using `unsafe` code.
+#[cfg(feature = "uninit")]
+#[allow(unsafe_code)]
+pub(crate) fn fill_uninit<'a, B: KeystreamBlock>(
+    b: &B,
+    out: &'a mut [core::mem::MaybeUninit<u8>],
+    start_offset: u64,
+) -> Result<&'a mut [u8], &'static str> {
+    if start_offset.checked_add(out.len() as u64).is_none() {
+        return Err("offset would overflow");
+    }
+    let mut block_offset = start_offset / B::BLOCK_SIZE as u64;
+    let mut offset_in_block = (start_offset % B::BLOCK_SIZE as u64) as usize;
+    let mut rest = &mut out[..];
+    while !rest.is_empty() {
+        let rate = b.block(block_offset);
+        let len = cmp::min(B::BLOCK_SIZE - offset_in_block, rest.len());
+        let (chunk, tail) = rest.split_at_mut(len);
+        for (x, &k) in chunk.iter_mut().zip(&rate.as_ref()[offset_in_block..]) {
+            x.write(k);
+        }
+        rest = tail;
+        block_offset += 1;
+        offset_in_block = 0;
+    }
+    // SAFETY: every byte of `out` has been initialized by the loop above,
+    // and `MaybeUninit<u8>` has the same layout as `u8`.
+    Ok(unsafe { &mut *(out as *mut [core::mem::MaybeUninit<u8>] as *mut [u8]) })
+}
+
+pub(crate) fn apply_keystream_inout<B: KeystreamBlock>(
+    b: &B,
+    input: &[u8],
+    output: &mut [u8],
+    start_offset: u64,
+) -> Result<(), &'static str> {
+    if input.len() != output.len() {
+        return Err("input and output lengths differ");
+    }
+    if start_offset.checked_add(output.len() as u64).is_none() {
+        return Err("offset would overflow");
+    }
+    let block_offset = start_offset / B::BLOCK_SIZE as u64;
+    let offset_in_first_block = (start_offset % B::BLOCK_SIZE as u64) as usize;
+    apply_from_block_inout(b, input, output, block_offset, offset_in_first_block);
+    Ok(())
+}
+
+#[cfg(feature = "inout")]
+pub(crate) fn apply_keystream_inout_buf<B: KeystreamBlock>(
+    b: &B,
+    mut buf: inout::InOutBuf<'_, '_, u8>,
+    start_offset: u64,
+) -> Result<(), &'static str> {
+    if start_offset.checked_add(buf.len() as u64).is_none() {
+        return Err("offset would overflow");
+    }
+    let mut block_offset = start_offset / B::BLOCK_SIZE as u64;
+    let mut offset_in_block = (start_offset % B::BLOCK_SIZE as u64) as usize;
+    while !buf.is_empty() {
+        let bytes_to_copy = cmp::min(B::BLOCK_SIZE - offset_in_block, buf.len());
+        let (mut chunk, rest) = buf.split_at(bytes_to_copy);
+        let rate = b.block(block_offset);
+        chunk.xor_in2out(&rate.as_ref()[offset_in_block..][..bytes_to_copy]);
+        buf = rest;
+        block_offset += 1;
+        offset_in_block = 0;
+    }
+    Ok(())
+}
+
+pub(crate) fn fill_wide<B: KeystreamBlock>(
+    b: &B,
+    out: &mut [u8],
+    start_offset: u128,
+) -> Result<(), &'static str> {
+    check_wide::<B>(out, start_offset)?;
+    let block_offset = (start_offset / B::BLOCK_SIZE as u128) as u64;
+    let offset_in_first_block = (start_offset % B::BLOCK_SIZE as u128) as usize;
+    fill_from_block(b, out, block_offset, offset_in_first_block);
+    Ok(())
+}
+
+pub(crate) fn apply_keystream_wide<B: KeystreamBlock>(
+    b: &B,
+    out: &mut [u8],
+    start_offset: u128,
+) -> Result<(), &'static str> {
+    check_wide::<B>(out, start_offset)?;
+    let block_offset = (start_offset / B::BLOCK_SIZE as u128) as u64;
+    let offset_in_first_block = (start_offset % B::BLOCK_SIZE as u128) as usize;
+    apply_from_block(b, out, block_offset, offset_in_first_block);
+    Ok(())
+}
+
+pub(crate) fn fill_blocks<B: KeystreamBlock>(
+    b: &B,
+    out: &mut [u8],
+    first_block: u64,
+) -> Result<(), &'static str> {
+    check_blocks::<B>(out, first_block)?;
+    fill_from_block(b, out, first_block, 0);
+    Ok(())
+}
+
+pub(crate) fn apply_blocks<B: KeystreamBlock>(
+    b: &B,
+    out: &mut [u8],
+    first_block: u64,
+) -> Result<(), &'static str> {
+    check_blocks::<B>(out, first_block)?;
+    apply_from_block(b, out, first_block, 0);
+    Ok(())
+}
+
+/// Check that a buffer starting at the given 128-bit offset doesn't go past the end of the key stream.
+fn check_wide<B: KeystreamBlock>(out: &[u8], start_offset: u128) -> Result<(), &'static str> {
+    if start_offset
+        .checked_add(out.len() as u128)
+        .is_none_or(|end| end > (B::BLOCK_SIZE as u128) << 64)
+    {
+        return Err("offset would overflow");
+    }
+    Ok(())
+}
+
+/// Check that a buffer starting at the given block doesn't go past the last block.
+fn check_blocks<B: KeystreamBlock>(out: &[u8], first_block: u64) -> Result<(), &'static str> {
+    if !out.is_empty()
+        && first_block
+            .checked_add(((out.len() - 1) / B::BLOCK_SIZE) as u64)
+            .is_none()
+    {
+        return Err("block index would overflow");
+    }
+    Ok(())
+}
+
+/// Fill the given buffer with the keystream, starting at the given block and offset in that block.
+pub(crate) fn fill_from_block<B: KeystreamBlock>(
+    b: &B,
+    mut out: &mut [u8],
+    mut block_offset: u64,
+    offset_in_first_block: usize,
+) {
+    let bytes_to_copy = cmp::min(B::BLOCK_SIZE - offset_in_first_block, out.len());
+    if bytes_to_copy > 0 {
+        let rate = b.block(block_offset);
+        out[..bytes_to_copy]
+            .copy_from_slice(&rate.as_ref()[offset_in_first_block..][..bytes_to_copy]);
+        out = &mut out[bytes_to_copy..];
+    }
+    while out.len() >= B::BLOCK_SIZE {
+        block_offset += 1;
+        b.store_block(&mut out[..B::BLOCK_SIZE], block_offset);
+        out = &mut out[B::BLOCK_SIZE..];
+    }
+    if !out.is_empty() {
+        block_offset += 1;
+        let rate = b.block(block_offset);
+        out.copy_from_slice(&rate.as_ref()[..out.len()]);
+    }
+}
+
+/// Add the keystream to the given buffer, starting at the given block and offset in that block.
+pub(crate) fn apply_from_block<B: KeystreamBlock>(
+    b: &B,
+    mut out: &mut [u8],
+    mut block_offset: u64,
+    offset_in_first_block: usize,
+) {
+    let bytes_to_copy = cmp::min(B::BLOCK_SIZE - offset_in_first_block, out.len());
+    if bytes_to_copy > 0 {
+        let rate = b.block(block_offset);
+        for (x, k) in out[..bytes_to_copy]
+            .iter_mut()
+            .zip(&rate.as_ref()[offset_in_first_block..])
+        {
+            *x ^= k;
+        }
+        out = &mut out[bytes_to_copy..];
+    }
+    while out.len() >= B::BLOCK_SIZE {
+        block_offset += 1;
+        b.xor_block(&mut out[..B::BLOCK_SIZE], block_offset);
+        out = &mut out[B::BLOCK_SIZE..];
+    }
+    if !out.is_empty() {
+        block_offset += 1;
+        let rate = b.block(block_offset);
+        for (x, k) in out.iter_mut().zip(rate.as_ref()) {
+            *x ^= k;
+        }
+    }
+}
+
+/// Add the keystream to `input` and store the result in `output`, starting at the given block and offset in that block.
+pub(crate) fn apply_from_block_inout<B: KeystreamBlock>(
+    b: &B,
+    mut input: &[u8],
+    mut output: &mut [u8],
+    mut block_offset: u64,
+    offset_in_first_block: usize,
+) {
+    let bytes_to_copy = cmp::min(B::BLOCK_SIZE - offset_in_first_block, output.len());
+    if bytes_to_copy > 0 {
+        let rate = b.block(block_offset);
+        for ((y, x), k) in output[..bytes_to_copy]
+            .iter_mut()
+            .zip(input)
+            .zip(&rate.as_ref()[offset_in_first_block..])
+        {
+            *y = x ^ k;
+        }
+        input = &input[bytes_to_copy..];
+        output = &mut output[bytes_to_copy..];
+    }
+    while output.len() >= B::BLOCK_SIZE {
+        block_offset += 1;
+        b.xor_block_inout(
+            &input[..B::BLOCK_SIZE],
+            &mut output[..B::BLOCK_SIZE],
+            block_offset,
+        );
+        input = &input[B::BLOCK_SIZE..];
+        output = &mut output[B::BLOCK_SIZE..];
+    }
+    if !output.is_empty() {
+        block_offset += 1;
+        let rate = b.block(block_offset);
+        for ((y, x), k) in output.iter_mut().zip(input).zip(rate.as_ref()) {
+            *y = x ^ k;
+        }
+    }
+}
+
+/// Return the number of remaining items of an iterator, as a size hint.
+fn remaining(start: u128, end: u128) -> (usize, Option<usize>) {
+    match usize::try_from(end - start) {
+        Ok(len) => (len, Some(len)),
+        Err(_) => (usize::MAX, None),
+    }
+}
+
+/// An iterator over the bytes of the key stream.
+///
+/// Seeking with `nth()` and `nth_back()` doesn't compute the skipped bytes.
+#[derive(Clone)]
+pub struct KeystreamBytes<B: KeystreamBlock> {
+    inner: B,
+    /// The offset of the next byte from the front
+    start: u128,
+    /// The offset following the next byte from the back
+    end: u128,
+    /// The last block used from the front
+    front: Option<(u64, B::Block)>,
+    /// The last block used from the back
+    back: Option<(u64, B::Block)>,
+}
+
+impl<B: KeystreamBlock> KeystreamBytes<B> {
+    /// Iterate over the bytes of the key stream from the given offset to offset `u64::MAX`.
+    pub(crate) fn new(inner: B, start_offset: u64) -> Self {
+        KeystreamBytes {
+            inner,
+            start: start_offset as u128,
+            end: u64::MAX as u128,
+            front: None,
+            back: None,
+        }
+    }
+
+    /// Iterate over `len` bytes of the key stream, truncated at offset `u64::MAX`.
+    pub(crate) fn new_range(inner: B, start_offset: u64, len: usize) -> Bounded<Self> {
+        let mut it = Self::new(inner, start_offset);
+        it.end = cmp::min(it.start + len as u128, it.end);
+        Bounded { inner: it }
+    }
+
+    /// Return the key stream byte at the given offset, using and updating a cached block.
+    fn byte(inner: &B, cache: &mut Option<(u64, B::Block)>, offset: u128) -> u8 {
+        let block_offset = (offset / B::BLOCK_SIZE as u128) as u64;
+        let offset_in_block = (offset % B::BLOCK_SIZE as u128) as usize;
+        match cache {
+            Some((cached_offset, block)) if *cached_offset == block_offset => {
+                block.as_ref()[offset_in_block]
+            }
+            _ => {
+                let block = inner.block(block_offset);
+                *cache = Some((block_offset, block));
+                block.as_ref()[offset_in_block]
+            }
+        }
+    }
+}
+
+impl<B: KeystreamBlock> Iterator for KeystreamBytes<B> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.start == self.end {
+            return None;
+        }
+        let byte = Self::byte(&self.inner, &mut self.front, self.start);
+        self.start += 1;
+        Some(byte)
+    }
+
+    fn nth(&mut self, n: usize) -> Option<u8> {
+        self.start = cmp::min(self.start + n as u128, self.end);
+        self.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        remaining(self.start, self.end)
+    }
+}
+
+impl<B: KeystreamBlock> DoubleEndedIterator for KeystreamBytes<B> {
+    fn next_back(&mut self) -> Option<u8> {
+        if self.start == self.end {
+            return None;
+        }
+        self.end -= 1;
+        Some(Self::byte(&self.inner, &mut self.back, self.end))
+    }
+
+    fn nth_back(&mut self, n: usize) -> Option<u8> {
+        self.end = cmp::max(self.end.saturating_sub(n as u128), self.start);
+        self.next_back()
+    }
+}
+
+impl<B: KeystreamBlock> FusedIterator for KeystreamBytes<B> {}
+
+/// An iterator over the key stream blocks.
+///
+/// Seeking with `nth()` and `nth_back()` doesn't compute the skipped blocks.
+#[derive(Clone)]
+pub struct KeystreamBlocks<B> {
+    inner: B,
+    /// The index of the next block from the front
+    start: u128,
+    /// The index following the next block from the back
+    end: u128,
+}
+
+impl<B: KeystreamBlock> KeystreamBlocks<B> {
+    /// Iterate over the key stream blocks from the given block to block `u64::MAX` included.
+    pub(crate) fn new(inner: B, first_block: u64) -> Self {
+        KeystreamBlocks {
+            inner,
+            start: first_block as u128,
+            end: 1 << 64,
+        }
+    }
+
+    /// Iterate over `count` key stream blocks, truncated after block `u64::MAX`.
+    pub(crate) fn new_range(inner: B, first_block: u64, count: usize) -> Bounded<Self> {
+        let mut it = Self::new(inner, first_block);
+        it.end = cmp::min(it.start + count as u128, it.end);
+        Bounded { inner: it }
+    }
+}
+
+impl<B: KeystreamBlock> Iterator for KeystreamBlocks<B> {
+    type Item = B::Block;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.start == self.end {
+            return None;
+        }
+        let block = self.inner.block(self.start as u64);
+        self.start += 1;
+        Some(block)
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.start = cmp::min(self.start + n as u128, self.end);
+        self.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        remaining(self.start, self.end)
+    }
+}
+
+impl<B: KeystreamBlock> DoubleEndedIterator for KeystreamBlocks<B> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.start == self.end {
+            return None;
+        }
+        self.end -= 1;
+        Some(self.inner.block(self.end as u64))
+    }
+
+    fn nth_back(&mut self, n: usize) -> Option<Self::Item> {
+        self.end = cmp::max(self.end.saturating_sub(n as u128), self.start);
+        self.next_back()
+    }
+}
+
+impl<B: KeystreamBlock> FusedIterator for KeystreamBlocks<B> {}
+
+/// A key stream iterator over a range whose length fits in a `usize`.
+///
+/// Unlike the unbounded iterators, it implements `ExactSizeIterator`.
+#[derive(Clone)]
+pub struct Bounded<I> {
+    inner: I,
+}
+
+impl<I: Iterator> Iterator for Bounded<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.inner.nth(n)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<I: DoubleEndedIterator> DoubleEndedIterator for Bounded<I> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back()
+    }
+
+    fn nth_back(&mut self, n: usize) -> Option<Self::Item> {
+        self.inner.nth_back(n)
+    }
+}
+
+impl<I: Iterator> ExactSizeIterator for Bounded<I> {}
+
+impl<I: FusedIterator> FusedIterator for Bounded<I> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascon::StreamCipher;
+
+    /// A block function only implementing the required method
+    #[derive(Clone, Copy)]
+    struct Blocks(StreamCipher);
+
+    impl KeystreamBlock for Blocks {
+        const BLOCK_SIZE: usize = StreamCipher::BLOCK_SIZE;
+        type Block = [u8; StreamCipher::BLOCK_SIZE];
+
+        fn block(&self, index: u64) -> Self::Block {
+            self.0.keystream_block(index)
+        }
+    }
+
+    #[test]
+    fn test_seekable() {
+        let mut key = [0u8; StreamCipher::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+
+        let st = StreamCipher::new(&key, b"test");
+        let seekable = Seekable::new(Blocks(st));
+
+        for (start, len) in [(0, 1000), (3, 1), (5, 40), (39, 82)] {
+            let mut expected = [0u8; 1000];
+            st.fill(&mut expected[..len], start).unwrap();
+            let mut out = [0u8; 1000];
+            seekable.fill(&mut out[..len], start).unwrap();
+            assert_eq!(out, expected);
+
+            seekable.apply_keystream(&mut out[..len], start).unwrap();
+            assert!(out.iter().all(|&x| x == 0));
+        }
+
+        let mut out = [0u8; 100];
+        let mut expected = [0u8; 100];
+        st.fill_blocks(&mut expected, 7).unwrap();
+        seekable.fill_blocks(&mut out, 7).unwrap();
+        assert_eq!(out, expected);
+        assert!(seekable.fill(&mut out, u64::MAX - 10).is_err());
+
+        let input = [0x42u8; 100];
+        seekable
+            .apply_keystream_inout(&input, &mut out, 39)
+            .unwrap();
+        st.apply_keystream_inout(&input, &mut expected, 39).unwrap();
+        assert_eq!(out, expected);
+
+        let mut blocks = seekable.keystream_blocks_range(u64::MAX - 1, 5);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks.next_back(), Some(st.keystream_block(u64::MAX)));
+        assert_eq!(seekable.keystream_blocks(u64::MAX).count(), 1);
+        assert!(seekable
+            .keystream_bytes_range(39, 100)
+            .eq(st.keystream_bytes(39).take(100)));
+    }
+}
@@ -0,0 +1,276 @@
+use crate::ascon::StreamCipher;
+use core::cmp;
+
+/// The state of an extent in a `KeyTable`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtentState {
+    /// No key has been assigned to the extent yet
+    Vacant,
+    /// The extent has a key, and can be encrypted and decrypted
+    Live,
+    /// The key of the extent has been destroyed, and its content cannot be decrypted any more
+    Shredded,
+}
+
+impl ExtentState {
+    fn from_tag(tag: u8) -> Result<Self, &'static str> {
+        match tag {
+            0 => Ok(ExtentState::Vacant),
+            1 => Ok(ExtentState::Live),
+            2 => Ok(ExtentState::Shredded),
+            _ => Err("invalid key table entry"),
+        }
+    }
+
+    fn tag(self) -> u8 {
+        match self {
+            ExtentState::Vacant => 0,
+            ExtentState::Live => 1,
+            ExtentState::Shredded => 2,
+        }
+    }
+}
+
+/// A table of per-extent keys, allowing parts of an object to be made permanently unrecoverable.
+///
+/// An object is split into fixed-size extents, each one being encrypted with its own key.
+/// Shredding an extent destroys its key, so that its content cannot be decrypted any more,
+/// without having to rewrite it.
+///
+/// The table is a plain byte slice owned by the application, with a 1-byte state followed by
+/// a 32-byte key per extent. It must be persisted by the application, and is required to decrypt
+/// the object.
+///
+/// # Caveats
+///
+/// * There is no integrity.
+/// * Keys must be randomly generated. Keys derived from a common secret cannot be shredded.
+///   For the same reason, they cannot come from a `KeyProvider`, that retains its keys.
+/// * Shredding only erases the key from this table. Previously persisted copies of the table must be destroyed as well.
+pub struct KeyTable<'a> {
+    /// The serialized entries
+    entries: &'a mut [u8],
+    /// The extent size in bytes
+    extent_size: u64,
+}
+
+impl<'a> KeyTable<'a> {
+    /// The key length in bytes
+    pub const KEY_LENGTH: usize = StreamCipher::KEY_LENGTH;
+
+    /// The size of a table entry in bytes
+    pub const ENTRY_LENGTH: usize = 1 + Self::KEY_LENGTH;
+
+    /// Use the given buffer as a key table, for extents of `extent_size` bytes.
+    ///
+    /// The buffer must be `ENTRY_LENGTH` bytes per extent. A new table must be filled with zeros,
+    /// and an existing table can be loaded by passing its previously persisted content.
+    pub fn new(entries: &'a mut [u8], extent_size: u64) -> Result<Self, &'static str> {
+        if extent_size == 0 {
+            return Err("extent size must not be zero");
+        }
+        if !entries.len().is_multiple_of(Self::ENTRY_LENGTH) {
+            return Err("invalid key table length");
+        }
+        for entry in entries.chunks_exact(Self::ENTRY_LENGTH) {
+            ExtentState::from_tag(entry[0])?;
+        }
+        Ok(KeyTable {
+            entries,
+            extent_size,
+        })
+    }
+
+    /// Return the number of extents tracked by the table.
+    pub fn extents(&self) -> u64 {
+        (self.entries.len() / Self::ENTRY_LENGTH) as u64
+    }
+
+    /// Return the extent size in bytes.
+    pub fn extent_size(&self) -> u64 {
+        self.extent_size
+    }
+
+    /// Return the state of the given extent.
+    pub fn state(&self, extent: u64) -> Result<ExtentState, &'static str> {
+        let entry = self.entry(extent)?;
+        ExtentState::from_tag(self.entries[entry])
+    }
+
+    /// List all the extents, along with their state.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, ExtentState)> + '_ {
+        self.entries
+            .chunks_exact(Self::ENTRY_LENGTH)
+            .enumerate()
+            .map(|(extent, entry)| (extent as u64, ExtentState::from_tag(entry[0]).unwrap()))
+    }
+
+    /// Assign a key to the given extent.
+    ///
+    /// The key must be 32 bytes long, and must be randomly generated.
+    /// The extent must be vacant: a shredded extent can never be given a key again.
+    pub fn insert(
+        &mut self,
+        extent: u64,
+        key: &[u8; StreamCipher::KEY_LENGTH],
+    ) -> Result<(), &'static str> {
+        match self.state(extent)? {
+            ExtentState::Vacant => {}
+            ExtentState::Live => return Err("extent already has a key"),
+            ExtentState::Shredded => return Err("extent key has been shredded"),
+        }
+        let entry = self.entry(extent)?;
+        self.entries[entry] = ExtentState::Live.tag();
+        self.entries[entry + 1..][..Self::KEY_LENGTH].copy_from_slice(key);
+        Ok(())
+    }
+
+    /// Destroy the key of the given extent.
+    ///
+    /// After this, the content of the extent cannot be decrypted any more.
+    pub fn shred(&mut self, extent: u64) -> Result<(), &'static str> {
+        let entry = self.entry(extent)?;
+        crate::zeroize(&mut self.entries[entry + 1..][..Self::KEY_LENGTH]);
+        self.entries[entry] = ExtentState::Shredded.tag();
+        Ok(())
+    }
+
+    /// Return the serialized table, to be persisted by the application.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.entries
+    }
+
+    /// Fill the given buffer with the keystream starting at the given offset.
+    ///
+    /// The offset is in bytes.
+    /// All the extents covered by the range must have a live key.
+    pub fn fill(&self, out: &mut [u8], start_offset: u64) -> Result<(), &'static str> {
+        self.process(out, start_offset, false)
+    }
+
+    /// Encrypt or decrypt the given buffer in place, given the offset.
+    ///
+    /// The offset is in bytes.
+    /// All the extents covered by the range must have a live key.
+    pub fn apply_keystream(&self, out: &mut [u8], start_offset: u64) -> Result<(), &'static str> {
+        self.process(out, start_offset, true)
+    }
+
+    /// Fill or encrypt a buffer, extent by extent.
+    fn process(
+        &self,
+        mut out: &mut [u8],
+        start_offset: u64,
+        xor: bool,
+    ) -> Result<(), &'static str> {
+        if start_offset.checked_add(out.len() as u64).is_none() {
+            return Err("offset would overflow");
+        }
+        if !out.is_empty() {
+            // Check that every extent has a live key before modifying the buffer
+            let last_extent = (start_offset + out.len() as u64 - 1) / self.extent_size;
+            for extent in start_offset / self.extent_size..=last_extent {
+                self.check_live(extent)?;
+            }
+        }
+        let mut offset = start_offset;
+        while !out.is_empty() {
+            let extent = offset / self.extent_size;
+            let offset_in_extent = offset % self.extent_size;
+            let len = cmp::min(self.extent_size - offset_in_extent, out.len() as u64) as usize;
+            let st = self.extent_cipher(extent)?;
+            let (chunk, rest) = out.split_at_mut(len);
+            if xor {
+                st.apply_keystream(chunk, offset_in_extent)?;
+            } else {
+                st.fill(chunk, offset_in_extent)?;
+            }
+            out = rest;
+            offset += len as u64;
+        }
+        Ok(())
+    }
+
+    fn check_live(&self, extent: u64) -> Result<(), &'static str> {
+        match self.state(extent)? {
+            ExtentState::Vacant => Err("extent has no key"),
+            ExtentState::Shredded => Err("extent key has been shredded"),
+            ExtentState::Live => Ok(()),
+        }
+    }
+
+    fn extent_cipher(&self, extent: u64) -> Result<StreamCipher, &'static str> {
+        self.check_live(extent)?;
+        let entry = self.entry(extent)?;
+        let key = self.entries[entry + 1..][..Self::KEY_LENGTH]
+            .try_into()
+            .unwrap();
+        Ok(StreamCipher::new(key, extent.to_le_bytes()))
+    }
+
+    fn entry(&self, extent: u64) -> Result<usize, &'static str> {
+        if extent >= self.extents() {
+            return Err("extent out of range");
+        }
+        Ok(extent as usize * Self::ENTRY_LENGTH)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shred() {
+        let mut storage = [0u8; 4 * KeyTable::ENTRY_LENGTH];
+        let mut table = KeyTable::new(&mut storage, 1000).unwrap();
+        for extent in 0..table.extents() {
+            let mut key = [0u8; KeyTable::KEY_LENGTH];
+            getrandom::fill(&mut key).unwrap();
+            table.insert(extent, &key).unwrap();
+        }
+
+        let msg = [0u8; 4000];
+        let mut c = msg;
+        table.apply_keystream(&mut c, 0).unwrap();
+
+        let mut p = [0u8; 1500];
+        p.copy_from_slice(&c[500..2000]);
+        table.apply_keystream(&mut p, 500).unwrap();
+        assert_eq!(p, msg[500..2000]);
+
+        table.shred(1).unwrap();
+        assert_eq!(table.state(1).unwrap(), ExtentState::Shredded);
+        let mut key = [0u8; KeyTable::KEY_LENGTH];
+        getrandom::fill(&mut key).unwrap();
+        assert!(table.insert(0, &key).is_err());
+        assert!(table.insert(1, &key).is_err());
+        assert_eq!(table.state(1).unwrap(), ExtentState::Shredded);
+        assert!(table.apply_keystream(&mut p, 500).is_err());
+        let mut partial = [0x42u8; 1000];
+        assert!(table.apply_keystream(&mut partial, 500).is_err());
+        assert_eq!(partial, [0x42u8; 1000]);
+        assert!(table.fill(&mut partial, 3500).is_err());
+        assert_eq!(partial, [0x42u8; 1000]);
+
+        let mut persisted = [0u8; 4 * KeyTable::ENTRY_LENGTH];
+        persisted.copy_from_slice(table.as_bytes());
+        let table = KeyTable::new(&mut persisted, 1000).unwrap();
+        assert!(table.iter().eq([
+            (0, ExtentState::Live),
+            (1, ExtentState::Shredded),
+            (2, ExtentState::Live),
+            (3, ExtentState::Live),
+        ]));
+        assert!(
+            table.as_bytes()[KeyTable::ENTRY_LENGTH + 1..][..KeyTable::KEY_LENGTH]
+                .iter()
+                .all(|&x| x == 0)
+        );
+
+        let mut p = [0u8; 1000];
+        p.copy_from_slice(&c[2000..3000]);
+        table.apply_keystream(&mut p, 2000).unwrap();
+        assert_eq!(p, msg[2000..3000]);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::ascon::{ContextBuilder, StreamCipher};
+use crate::key::Key;
+use crate::provider::KeyProvider;
+
+/// Deterministic key wrapping, for storing data keys next to the data they protect.
+///
+/// Keys are wrapped with a key-encryption key (KEK) and a label, using an SIV construction:
+///
+/// * `tag = PRF(K1, label || key)`
+/// * `wrapped = tag || key ^ keystream(K2, tag)`
+///
+/// The PRF and the keystream are both built on the Ascon-based stream cipher,
+/// and `(K1, K2)` are derived from the KEK.
+///
+/// Unwrapping verifies the tag, so a wrapped key that has been modified, or that is unwrapped
+/// with a different KEK or label, is rejected.
+///
+/// # Caveats
+///
+/// * Wrapping is deterministic: wrapping the same key with the same label twice produces the same output.
+///   This is fine for random keys, but reveals when the same key has been wrapped multiple times.
+#[derive(Clone)]
+pub struct KeyWrap {
+    /// The key used to compute tags
+    mac_key: [u8; StreamCipher::KEY_LENGTH],
+    /// The key used to encrypt keys
+    enc_key: [u8; StreamCipher::KEY_LENGTH],
+}
+
+impl KeyWrap {
+    /// The key-encryption key length in bytes
+    pub const KEY_LENGTH: usize = StreamCipher::KEY_LENGTH;
+
+    /// The tag length in bytes
+    pub const TAG_LENGTH: usize = 16;
+
+    /// The length of a wrapped key in bytes
+    pub const WRAPPED_KEY_LENGTH: usize = Self::TAG_LENGTH + Key::LENGTH;
+
+    /// The length of a wrapped key prefixed with the ID of the key-encryption key, in bytes
+    pub const WRAPPED_KEY_WITH_ID_LENGTH: usize = 8 + Self::WRAPPED_KEY_LENGTH;
+
+    /// Create a key wrapper from a key-encryption key.
+    ///
+    /// The key must be 32 bytes long, and must be randomly generated.
+    /// It should only be used to wrap keys.
+    pub fn new(kek: &[u8; Self::KEY_LENGTH]) -> Self {
+        let st = StreamCipher::new(kek, b"seekable-stream-cipher/key-wrap");
+        let mut mac_key = [0u8; StreamCipher::KEY_LENGTH];
+        let mut enc_key = [0u8; StreamCipher::KEY_LENGTH];
+        st.fill(&mut mac_key, 0).unwrap();
+        st.fill(&mut enc_key, StreamCipher::KEY_LENGTH as u64)
+            .unwrap();
+        KeyWrap { mac_key, enc_key }
+    }
+
+    /// Wrap a key, using the given label.
+    ///
+    /// The label is optional and can be of any length. The same label is required to unwrap the key.
+    pub fn wrap_key(
+        &self,
+        key: &[u8; Key::LENGTH],
+        label: impl AsRef<[u8]>,
+    ) -> [u8; Self::WRAPPED_KEY_LENGTH] {
+        let tag = self.tag(key, label.as_ref());
+        let mut wrapped = [0u8; Self::WRAPPED_KEY_LENGTH];
+        wrapped[..Self::TAG_LENGTH].copy_from_slice(&tag);
+        wrapped[Self::TAG_LENGTH..].copy_from_slice(key);
+        StreamCipher::new(&self.enc_key, tag)
+            .apply_keystream(&mut wrapped[Self::TAG_LENGTH..], 0)
+            .unwrap();
+        wrapped
+    }
+
+    /// Unwrap a key previously wrapped with the same key-encryption key and label.
+    pub fn unwrap_key(&self, wrapped: &[u8], label: impl AsRef<[u8]>) -> Result<Key, &'static str> {
+        if wrapped.len() != Self::WRAPPED_KEY_LENGTH {
+            return Err("invalid wrapped key length");
+        }
+        let (tag, ciphertext) = wrapped.split_at(Self::TAG_LENGTH);
+        let mut key = [0u8; Key::LENGTH];
+        key.copy_from_slice(ciphertext);
+        StreamCipher::new(&self.enc_key, tag).apply_keystream(&mut key, 0)?;
+        let expected_tag = self.tag(&key, label.as_ref());
+        let valid = crate::ct_eq(tag, &expected_tag);
+        let unwrapped = Key::from(key);
+        crate::zeroize(&mut key);
+        if !valid {
+            return Err("invalid wrapped key");
+        }
+        Ok(unwrapped)
+    }
+
+    /// Wrap a key with the active key of a key provider.
+    ///
+    /// The output starts with the ID of the key-encryption key, encoded as a 64-bit little-endian
+    /// integer, so that `unwrap_key_with_provider()` can find it again after the active key has been rotated.
+    pub fn wrap_key_with_provider(
+        provider: &impl KeyProvider,
+        key: &[u8; Key::LENGTH],
+        label: impl AsRef<[u8]>,
+    ) -> Result<[u8; Self::WRAPPED_KEY_WITH_ID_LENGTH], &'static str> {
+        let (kek_id, kek) = provider.active_key()?;
+        let mut out = [0u8; Self::WRAPPED_KEY_WITH_ID_LENGTH];
+        out[..8].copy_from_slice(&kek_id.to_le_bytes());
+        out[8..].copy_from_slice(&KeyWrap::new(&kek).wrap_key(key, label));
+        Ok(out)
+    }
+
+    /// Unwrap a key wrapped with `wrap_key_with_provider()`, using the key-encryption key it records.
+    pub fn unwrap_key_with_provider(
+        provider: &impl KeyProvider,
+        wrapped: &[u8],
+        label: impl AsRef<[u8]>,
+    ) -> Result<Key, &'static str> {
+        if wrapped.len() != Self::WRAPPED_KEY_WITH_ID_LENGTH {
+            return Err("invalid wrapped key length");
+        }
+        let kek = provider.key(crate::load_u64_le(wrapped, 0))?;
+        KeyWrap::new(&kek).unwrap_key(&wrapped[8..], label)
+    }
+
+    /// Compute the tag of a key.
+    fn tag(&self, key: &[u8; Key::LENGTH], label: &[u8]) -> [u8; Self::TAG_LENGTH] {
+        let mut tag = [0u8; Self::TAG_LENGTH];
+        ContextBuilder::new(&self.mac_key)
+            .part(label)
+            .part(key)
+            .finish()
+            .fill(&mut tag, 0)
+            .unwrap();
+        tag
+    }
+}
+
+impl Drop for KeyWrap {
+    fn drop(&mut self) {
+        crate::zeroize(&mut self.mac_key);
+        crate::zeroize(&mut self.enc_key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap() {
+        let mut kek = [0u8; KeyWrap::KEY_LENGTH];
+        getrandom::fill(&mut kek).unwrap();
+        let mut data_key = [0u8; Key::LENGTH];
+        getrandom::fill(&mut data_key).unwrap();
+
+        let kw = KeyWrap::new(&kek);
+        let wrapped = kw.wrap_key(&data_key, b"object 42");
+        assert!(wrapped[KeyWrap::TAG_LENGTH..] != data_key);
+        assert_eq!(wrapped, kw.wrap_key(&data_key, b"object 42"));
+        assert_eq!(
+            kw.unwrap_key(&wrapped, b"object 42").unwrap(),
+            Key::from(data_key)
+        );
+
+        assert!(kw.unwrap_key(&wrapped, b"object 43").is_err());
+        let mut tampered = wrapped;
+        tampered[40] ^= 1;
+        assert!(kw.unwrap_key(&tampered, b"object 42").is_err());
+        assert!(kw.unwrap_key(&wrapped[1..], b"object 42").is_err());
+
+        kek[0] ^= 1;
+        assert!(KeyWrap::new(&kek)
+            .unwrap_key(&wrapped, b"object 42")
+            .is_err());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_wrap_provider() {
+        use crate::provider::MemoryKeyProvider;
+
+        let mut provider = MemoryKeyProvider::new();
+        provider.rotate(Key::from([1u8; Key::LENGTH])).unwrap();
+        let data_key = [3u8; Key::LENGTH];
+        let wrapped = KeyWrap::wrap_key_with_provider(&provider, &data_key, b"label").unwrap();
+
+        provider.rotate(Key::from([2u8; Key::LENGTH])).unwrap();
+        assert_eq!(
+            KeyWrap::unwrap_key_with_provider(&provider, &wrapped, b"label").unwrap(),
+            Key::from(data_key)
+        );
+        let wrapped2 = KeyWrap::wrap_key_with_provider(&provider, &data_key, b"label").unwrap();
+        assert_eq!(wrapped2[..8], 1u64.to_le_bytes());
+        assert!(KeyWrap::unwrap_key_with_provider(&provider, &wrapped2[1..], b"label").is_err());
+    }
+}